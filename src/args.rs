@@ -1,19 +1,150 @@
 //! Argument parsing.
+use std::path::PathBuf;
+
 use argh::FromArgs;
 
+use crate::common::InstanceSpec;
+use crate::credential::TokenFile;
+use crate::report::ReportFormat;
+
 #[derive(Debug, FromArgs)]
 /// Star your upstream.
 pub struct Args {
     /// do not actually star upstream repositories
     #[argh(switch, short = 'd')]
     pub dry_run: bool,
+    /// star upstream repositories even if `dry-run` is set in a config file
+    #[argh(switch)]
+    pub no_dry_run: bool,
     /// suppress all output
     #[argh(switch, short = 'q')]
     pub quiet: bool,
     /// ignore persisted states (like credentials)
     #[argh(switch)]
     pub ignore_saved: bool,
+    /// skip packages successfully starred in previous runs
+    #[argh(switch)]
+    pub incremental: bool,
+    /// star all packages even if `--incremental` is given
+    #[argh(switch)]
+    pub full: bool,
+    /// only use cached registry metadata, never query registries
+    #[argh(switch)]
+    pub offline: bool,
+    /// ignore cached registry metadata and query registries again
+    #[argh(switch)]
+    pub refresh: bool,
+    /// seconds before cached registry metadata is revalidated (see `stars config show`)
+    #[argh(option)]
+    pub cache_ttl: Option<u64>,
+    /// look up upstream of unrecognized system packages on Repology
+    #[argh(switch)]
+    pub repology: bool,
+    /// don't look up packages on Repology even if `repology` is set in a config file
+    #[argh(switch)]
+    pub no_repology: bool,
+    /// crawl homepages of unrecognized packages for repository links
+    #[argh(switch)]
+    pub crawl: bool,
+    /// don't crawl homepages even if `crawl` is set in a config file
+    #[argh(switch)]
+    pub no_crawl: bool,
+    /// resolve renamed or transferred repositories before starring
+    #[argh(switch)]
+    pub canonicalize: bool,
+    /// don't resolve repositories even if `canonicalize` is set in a config file
+    #[argh(switch)]
+    pub no_canonicalize: bool,
+    /// list packages whose upstream is not recognized by any target
+    #[argh(switch)]
+    pub unresolved: bool,
+    /// don't list unresolved packages even if `unresolved` is set in a config file
+    #[argh(switch)]
+    pub no_unresolved: bool,
+    /// write a report of all discovered packages to the given path
+    #[argh(option)]
+    pub report: Option<PathBuf>,
+    /// format of the report (json, csv, markdown). Guessed from the extension by default
+    #[argh(option)]
+    pub format: Option<ReportFormat>,
+    /// add a self-hosted GitLab instance as target, in the form of `name=base_url`
+    #[argh(option)]
+    pub gitlab: Vec<InstanceSpec>,
+    /// add a Gitea or Forgejo instance as target, in the form of `name=base_url`
+    #[argh(option)]
+    pub gitea: Vec<InstanceSpec>,
+    /// log in to github.com with the device flow of the given OAuth app instead of asking for a
+    /// token. The device flow needs one, and is not available on GitHub Enterprise instances
+    #[argh(option)]
+    pub github_client_id: Option<String>,
+    /// add a GitHub Enterprise Server instance as target, in the form of `name=base_url`
+    #[argh(option)]
+    pub github_enterprise: Vec<InstanceSpec>,
+    /// star with the given account profile, repeatable and remembered (`default` is the default account)
+    #[argh(option)]
+    pub profile: Vec<String>,
+    /// read the token of a target from a file, in the form of `target=path`
+    #[argh(option)]
+    pub token_file: Vec<TokenFile>,
+    /// only star packages matching the rule, in the form of `[field:]pattern`, e.g. `owner:acme` (repeatable)
+    #[argh(option)]
+    pub include: Vec<String>,
+    /// never star packages matching the rule, in the form of `[field:]pattern`, e.g. `repo:*/fork-*` (repeatable)
+    #[argh(option)]
+    pub exclude: Vec<String>,
+    /// review and pick packages before starring, remembering deselected ones as ignored
+    #[argh(switch, short = 'i')]
+    pub interactive: bool,
     /// disable specific modules
     #[argh(option)]
     pub disable: Vec<String>,
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    History(HistoryArgs),
+    Config(ConfigArgs),
+}
+
+#[derive(Debug, FromArgs)]
+/// Show what was starred in past runs.
+#[argh(subcommand, name = "history")]
+pub struct HistoryArgs {
+    /// only show records of the last run
+    #[argh(switch)]
+    pub last_run: bool,
+    /// only show records of specific target
+    #[argh(option)]
+    pub target: Option<String>,
+    /// only show records of specific source
+    #[argh(option)]
+    pub source: Option<String>,
+    /// only show records with specific outcome (starred, already_starred, archived, not_found, failed, skipped)
+    #[argh(option)]
+    pub outcome: Option<String>,
+    /// only show the last N records
+    #[argh(option)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, FromArgs)]
+/// Inspect the configuration.
+#[argh(subcommand, name = "config")]
+pub struct ConfigArgs {
+    #[argh(subcommand)]
+    pub command: ConfigCommand,
 }
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+pub enum ConfigCommand {
+    Show(ConfigShowArgs),
+}
+
+#[derive(Debug, FromArgs)]
+/// Print the effective configuration, merged from config files and flags.
+#[argh(subcommand, name = "show")]
+pub struct ConfigShowArgs {}
@@ -0,0 +1,77 @@
+//! Bitbucket integration.
+//!
+//! Bitbucket Cloud has no stars, and watching repositories is not available in its current API.
+//! Packages are still claimed so that they show up as recognized instead of unresolved.
+
+use itertools::Itertools;
+use url::Url;
+
+use crate::common::{Error, Package, StarStatus, Target};
+use crate::credential::Credentials;
+use crate::normalize::{normalize, trim_git_suffix};
+use crate::{Logger, Persist};
+
+/// Top-level paths of bitbucket.org that are not workspaces.
+const RESERVED_WORKSPACES: &[&str] = &["account", "dashboard", "product", "repo", "site"];
+
+pub struct Bitbucket;
+
+impl Target for Bitbucket {
+    fn name(&self) -> &'static str {
+        "bitbucket"
+    }
+
+    fn init(
+        &mut self,
+        _logger: &Logger,
+        _persist: &mut Persist,
+        _credentials: &Credentials,
+    ) -> bool {
+        true
+    }
+
+    fn try_handle(&self, url: &Url) -> Option<String> {
+        let forge = normalize(url)?;
+        if forge.host != "bitbucket.org" {
+            return None;
+        }
+
+        let (workspace, repo) = forge.segments.iter().take(2).collect_tuple()?;
+        if RESERVED_WORKSPACES.contains(&workspace.to_lowercase().as_str()) {
+            return None;
+        }
+        // Bitbucket paths are case-insensitive.
+        Some(format!("{}/{}", workspace, trim_git_suffix(repo)).to_lowercase())
+    }
+
+    fn star(&self, _logger: &Logger, _package: &Package) -> Result<StarStatus, Error> {
+        Ok(StarStatus::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::Target;
+    use crate::normalize::parse_url;
+
+    use super::Bitbucket;
+
+    #[test]
+    fn test_try_handle() {
+        let cases = [
+            ("https://bitbucket.org/owner/repo", Some("owner/repo")),
+            ("https://bitbucket.org/Owner/Repo.git", Some("owner/repo")),
+            ("git@bitbucket.org:owner/repo.git", Some("owner/repo")),
+            (
+                "https://bitbucket.org/owner/repo/downloads/repo-1.0.tar.gz",
+                Some("owner/repo"),
+            ),
+            ("https://bitbucket.org/owner", None),
+            ("https://bitbucket.org/account/signin", None),
+        ];
+        for (input, expected) in cases {
+            let url = parse_url(input).unwrap();
+            assert_eq!(Bitbucket.try_handle(&url).as_deref(), expected, "{}", input);
+        }
+    }
+}
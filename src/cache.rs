@@ -0,0 +1,219 @@
+//! On-disk cache for registry metadata.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use attohttpc::header::{ETAG, IF_NONE_MATCH};
+use attohttpc::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{project_dirs, unix_now, BoxedError, HTTP};
+
+/// Default time-to-live of cached entries.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How the cache is consulted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CacheMode {
+    /// Use fresh entries, and revalidate stale ones with the server.
+    Normal,
+    /// Only use cached entries. Never touch the network.
+    Offline,
+    /// Ignore cached entries and always fetch. Fetched entries are still saved.
+    Refresh,
+}
+
+/// Cache for HTTP responses of package registries.
+///
+/// Entries are keyed by registry and package name, and stored as one file per entry under the
+/// cache directory. Stale entries are revalidated with `If-None-Match` if the server gave an `ETag`.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    mode: CacheMode,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(Self::default_dir(), DEFAULT_TTL, CacheMode::Normal)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    etag: Option<String>,
+    fetched_at: u64,
+    body: String,
+}
+
+impl Cache {
+    /// Create a cache stored in the given directory.
+    pub fn new(dir: impl AsRef<Path>, ttl: Duration, mode: CacheMode) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            ttl,
+            mode,
+        }
+    }
+    /// Default cache directory.
+    pub fn default_dir() -> PathBuf {
+        project_dirs().cache_dir().join("http")
+    }
+    /// Fetch `url` through the cache and return the response body.
+    ///
+    /// `registry` and `key` identify the entry, e.g. `crates.io` and the crate name.
+    pub fn get(&self, registry: &str, key: &str, url: &str) -> Result<String, BoxedError> {
+        let path = self.entry_path(registry, key);
+        let cached = if self.mode == CacheMode::Refresh {
+            None
+        } else {
+            load(&path)
+        };
+
+        match (self.mode, cached) {
+            (CacheMode::Offline, Some(entry)) => Ok(entry.body),
+            (CacheMode::Offline, None) => {
+                Err(format!("{} is not cached for {} (offline mode)", key, registry).into())
+            }
+            (_, Some(entry))
+                if unix_now().saturating_sub(entry.fetched_at) < self.ttl.as_secs() =>
+            {
+                Ok(entry.body)
+            }
+            (_, cached) => {
+                let mut req = HTTP.get(url);
+                if let Some(etag) = cached.as_ref().and_then(|entry| entry.etag.as_deref()) {
+                    req = req.header(IF_NONE_MATCH, etag);
+                }
+                let resp = req.send()?;
+
+                if resp.status() == StatusCode::NOT_MODIFIED {
+                    if let Some(mut entry) = cached {
+                        entry.fetched_at = unix_now();
+                        store(&path, &entry);
+                        return Ok(entry.body);
+                    }
+                }
+                if !resp.status().is_success() {
+                    return Err(format!("{} {}", resp.status(), url).into());
+                }
+
+                let etag = resp
+                    .headers()
+                    .get(ETAG)
+                    .and_then(|value| value.to_str().ok())
+                    .map(ToString::to_string);
+                let entry = Entry {
+                    etag,
+                    fetched_at: unix_now(),
+                    body: resp.text()?,
+                };
+                store(&path, &entry);
+                Ok(entry.body)
+            }
+        }
+    }
+    /// Whether a fresh entry exists, i.e. [`get`](Self::get) won't touch the network.
+    pub fn is_fresh(&self, registry: &str, key: &str) -> bool {
+        match self.mode {
+            CacheMode::Offline => true,
+            CacheMode::Refresh => false,
+            CacheMode::Normal => load(&self.entry_path(registry, key)).is_some_and(|entry| {
+                unix_now().saturating_sub(entry.fetched_at) < self.ttl.as_secs()
+            }),
+        }
+    }
+    /// Store a body for the entry without fetching it, e.g. to remember a failed request until
+    /// the entry goes stale. Offline runs leave the cache untouched.
+    pub fn put(&self, registry: &str, key: &str, body: String) {
+        if self.mode != CacheMode::Offline {
+            let entry = Entry {
+                etag: None,
+                fetched_at: unix_now(),
+                body,
+            };
+            store(&self.entry_path(registry, key), &entry);
+        }
+    }
+    /// Fetch `url` through the cache and deserialize the response body as json.
+    pub fn get_json<T: DeserializeOwned>(
+        &self,
+        registry: &str,
+        key: &str,
+        url: &str,
+    ) -> Result<T, BoxedError> {
+        Ok(serde_json::from_str(&self.get(registry, key, url)?)?)
+    }
+
+    fn entry_path(&self, registry: &str, key: &str) -> PathBuf {
+        self.dir
+            .join(urlencoding::encode(registry).as_ref())
+            .join(format!("{}.json", urlencoding::encode(key)))
+    }
+}
+
+fn load(path: &Path) -> Option<Entry> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+fn store(path: &Path, entry: &Entry) {
+    // A failed write only costs a refetch next time, so errors are ignored.
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = fs::write(path, serde_json::to_vec(entry).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::common::unix_now;
+
+    use super::{store, Cache, CacheMode, Entry};
+
+    #[test]
+    fn test_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path(), Duration::ZERO, CacheMode::Offline);
+        store(
+            &cache.entry_path("crates.io", "serde"),
+            &Entry {
+                etag: None,
+                fetched_at: 0,
+                body: String::from("{}"),
+            },
+        );
+
+        // Stale entries are still served in offline mode.
+        assert_eq!(
+            cache.get("crates.io", "serde", "http://invalid").unwrap(),
+            "{}"
+        );
+        assert!(cache.get("crates.io", "tap", "http://invalid").is_err());
+    }
+
+    #[test]
+    fn test_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path(), Duration::from_secs(60), CacheMode::Normal);
+        store(
+            &cache.entry_path("crates.io", "serde"),
+            &Entry {
+                etag: None,
+                fetched_at: unix_now(),
+                body: String::from("{}"),
+            },
+        );
+
+        assert_eq!(
+            cache.get("crates.io", "serde", "http://invalid").unwrap(),
+            "{}"
+        );
+        let refresh = Cache::new(dir.path(), Duration::from_secs(60), CacheMode::Refresh);
+        assert!(refresh.get("crates.io", "serde", "http://invalid").is_err());
+    }
+}
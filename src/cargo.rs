@@ -6,14 +6,24 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::Deserialize;
 use tap::TapFallible;
-use url::Url;
 
-use crate::common::{BoxedError, Package, Source, SourceType, HTTP};
-use crate::{Logger, TargetRegistry};
+use crate::cache::Cache;
+use crate::common::{run_command, BoxedError, Candidate, Error, Source, SourceType};
+use crate::normalize::parse_url;
+use crate::Logger;
 
 static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?m)^[a-zA-Z][a-zA-Z0-9-_]*").unwrap());
 
-pub struct CargoGlobal;
+#[derive(Default)]
+pub struct CargoGlobal {
+    cache: Cache,
+}
+
+impl CargoGlobal {
+    pub const fn new(cache: Cache) -> Self {
+        Self { cache }
+    }
+}
 
 impl Source for CargoGlobal {
     fn name(&self) -> &'static str {
@@ -32,14 +42,9 @@ impl Source for CargoGlobal {
         &self,
         logger: &Logger,
         _files: HashMap<&str, &[u8]>,
-        targets: &TargetRegistry,
-    ) -> Result<Vec<Package>, BoxedError> {
-        let raw_output = Command::new("cargo")
-            .arg("install")
-            .arg("--list")
-            .output()?
-            .stdout;
-        let output = str::from_utf8(&*raw_output)?;
+    ) -> Result<Vec<Candidate>, Error> {
+        let raw_output = run_command(Command::new("cargo").arg("install").arg("--list"))?;
+        let output = str::from_utf8(&raw_output)?;
 
         let crates: Vec<_> = RE
             .find_iter(output)
@@ -48,11 +53,20 @@ impl Source for CargoGlobal {
 
         logger.set_progress_bar_determinate(crates.len() as u64);
 
-        Ok(fetch_crates_meta(logger, targets, &crates))
+        Ok(fetch_crates_meta(logger, &self.cache, &crates))
     }
 }
 
-pub struct Cargo;
+#[derive(Default)]
+pub struct Cargo {
+    cache: Cache,
+}
+
+impl Cargo {
+    pub const fn new(cache: Cache) -> Self {
+        Self { cache }
+    }
+}
 
 impl Source for Cargo {
     fn name(&self) -> &'static str {
@@ -71,18 +85,17 @@ impl Source for Cargo {
         &self,
         logger: &Logger,
         files: HashMap<&str, &[u8]>,
-        targets: &TargetRegistry,
-    ) -> Result<Vec<Package>, BoxedError> {
+    ) -> Result<Vec<Candidate>, Error> {
         let raw_cargo_toml = files.get("Cargo.toml").unwrap();
         let cargo_toml: CargoToml = toml::from_slice(raw_cargo_toml)?;
         let crates: Vec<_> = cargo_toml
             .all_dependencies()
-            .map(|(key, value)| entry_to_name(&*key, value).to_string())
+            .map(|(key, value)| entry_to_name(key, value).to_string())
             .collect();
 
         logger.set_progress_bar_determinate(crates.len() as u64);
 
-        Ok(fetch_crates_meta(logger, targets, &crates))
+        Ok(fetch_crates_meta(logger, &self.cache, &crates))
     }
 }
 
@@ -94,35 +107,36 @@ fn entry_to_name<'a>(key: &'a str, value: &'a CrateValue) -> &'a str {
     }
 }
 
-fn fetch_crates_meta(logger: &Logger, targets: &TargetRegistry, crates: &[String]) -> Vec<Package> {
+fn fetch_crates_meta(logger: &Logger, cache: &Cache, crates: &[String]) -> Vec<Candidate> {
     crates
         .iter()
-        .filter_map(|name| {
+        .map(|name| {
             logger.set_message(name.as_str());
-            let crate_ = query_crate(name.as_str())
+            let urls = query_crate(cache, name.as_str())
                 .tap_err(|e| {
-                    logger.error(&format!(
+                    logger.error(format!(
                         "Failed to query metadata for crate {}: {}",
                         name, e
                     ));
                 })
-                .ok()?;
+                .map(|crate_| {
+                    crate_
+                        .homepage
+                        .into_iter()
+                        .chain(crate_.repository)
+                        .filter_map(|url| parse_url(&url))
+                        .collect()
+                })
+                .unwrap_or_default();
             logger.with_progress_bar(|pb| pb.inc(1));
-            Some((name, crate_))
-        })
-        .filter_map(|(name, crate_)| {
-            crate_
-                .homepage
-                .into_iter()
-                .chain(crate_.repository)
-                .find_map(|url| targets.try_parse(name.clone(), &url))
+            Candidate::new(name.clone(), urls)
         })
         .collect()
 }
 
-fn query_crate(name: &str) -> Result<Crate, BoxedError> {
+fn query_crate(cache: &Cache, name: &str) -> Result<Crate, BoxedError> {
     let url = format!("https://crates.io/api/v1/crates/{}", name);
-    let resp: Resp = HTTP.get(&url).send()?.json()?;
+    let resp: Resp = cache.get_json("crates.io", name, &url)?;
     Ok(resp.crate_data)
 }
 
@@ -134,8 +148,8 @@ struct Resp {
 
 #[derive(Deserialize)]
 struct Crate {
-    homepage: Option<Url>,
-    repository: Option<Url>,
+    homepage: Option<String>,
+    repository: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -161,7 +175,7 @@ impl CargoToml {
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 enum CrateValue {
-    Ver(String),
+    Ver(#[allow(dead_code)] String),
     Map { name: Option<String> },
 }
 
@@ -179,7 +193,7 @@ mod tests {
     #[test]
     fn test_cargo() {
         test_source(
-            &Cargo,
+            &Cargo::default(),
             hashmap! {
                 "Cargo.toml" => &include_bytes!("../Cargo.toml")[..],
             },
@@ -189,7 +203,7 @@ mod tests {
 
     #[test]
     fn test_cargo_global() {
-        test_source(&CargoGlobal, HashMap::new(), |packages| {
+        test_source(&CargoGlobal::default(), HashMap::new(), |packages| {
             assert!(!packages.is_empty());
         });
     }
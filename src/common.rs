@@ -1,14 +1,25 @@
 //! Definitions of common structs, enums and traits.
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::process::Command;
+use std::str::{FromStr, Utf8Error};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use attohttpc::header::USER_AGENT;
-use attohttpc::Session;
+use attohttpc::{Response, Session, StatusCode};
+use console::style;
+use directories::ProjectDirs;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::registry::TargetRegistry;
+use crate::credential::Credentials;
+use crate::normalize::normalize;
+use crate::repology::RepologyRepo;
 use crate::{Logger, Persist};
 
 /// Global available HTTP client.
@@ -21,6 +32,46 @@ pub static HTTP: Lazy<Session> = Lazy::new(|| {
     session
 });
 
+/// Platform-specific directories of this program.
+pub fn project_dirs() -> &'static ProjectDirs {
+    static DIRS: Lazy<ProjectDirs> =
+        Lazy::new(|| ProjectDirs::from("me", "lightquantum", "stars").unwrap());
+    &DIRS
+}
+
+/// Current unix timestamp in seconds.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Leak a target or profile name, which lives as long as the program.
+pub fn leak_name(name: String) -> &'static str {
+    Box::leak(name.into_boxed_str())
+}
+
+/// Name of the target an account belongs to, stripping the `@profile` suffix if any.
+pub fn base_name(target: &str) -> &str {
+    target.split_once('@').map_or(target, |(base, _)| base)
+}
+
+/// Warn that star actions are public, before asking for a credential.
+pub fn warn_public(logger: &Logger) {
+    logger.warn("Beware that star actions will be publicly visible.");
+    logger.warn("To avoid polluting your timeline, consider using a dedicated account.");
+}
+
+/// Fingerprint of a credential, used to detect credential changes without storing it again.
+///
+/// The hash is not guaranteed to be stable across Rust releases, which at worst invalidates
+/// states bound to the credential.
+pub fn fingerprint(credential: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    credential.hash(&mut hasher);
+    hasher.finish()
+}
+
 /// Convenient alias for boxed error.
 pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 
@@ -33,12 +84,34 @@ pub trait Source: 'static {
     /// Check whether the source is available on this system.
     fn available(&self) -> bool;
     /// Snapshot of the source.
+    ///
+    /// Return all packages found, including those without any recognizable url.
     fn snapshot(
         &self,
         logger: &Logger,
         files: HashMap<&str, &[u8]>,
-        targets: &TargetRegistry,
-    ) -> Result<Vec<Package>, BoxedError>;
+    ) -> Result<Vec<Candidate>, Error>;
+    /// Repository of this source on Repology, if it's tracked there.
+    ///
+    /// Used by [`Repology`](crate::repology::Repology) to map package names to upstream projects.
+    fn repology_repo(&self) -> Option<RepologyRepo> {
+        None
+    }
+}
+
+/// Resolver to find more urls for packages not recognized by any target.
+pub trait Resolver: 'static {
+    /// Identifier of this resolver.
+    fn name(&self) -> &'static str;
+    /// Find more candidate urls of the package discovered by `source`.
+    ///
+    /// Urls already in the candidate have been tried and needn't be returned again.
+    fn resolve(
+        &self,
+        logger: &Logger,
+        source: &dyn Source,
+        candidate: &Candidate,
+    ) -> Result<Vec<Url>, BoxedError>;
 }
 
 /// Type of the source.
@@ -59,36 +132,367 @@ pub trait Target: 'static {
     ///
     /// This function will be called first time this target is used to star a package.
     /// `persist` struct can be used to save states across multiple runs.
+    /// Credentials should be looked up through `credentials`.
     ///
     /// Return `true` to indicate a success.
-    fn init(&mut self, logger: &Logger, persist: &mut Persist) -> bool;
+    fn init(&mut self, logger: &Logger, persist: &mut Persist, credentials: &Credentials) -> bool;
     /// Check whether the url can be handled by this target.
     /// If can, extract identifier specific to this target from the url.
     ///
     /// This function should not touch the system or issue any network requests.
     fn try_handle(&self, url: &Url) -> Option<String>;
+    /// Fingerprint of the credential in use, if any.
+    ///
+    /// States bound to this target (e.g., incremental progress) are invalidated when it changes.
+    fn fingerprint(&self) -> Option<u64> {
+        None
+    }
+    /// Look up the canonical identifier of the package, following renames and transfers.
+    ///
+    /// The default implementation assumes identifiers are canonical.
+    fn canonicalize(&self, _logger: &Logger, package: &Package) -> Result<Canonical, BoxedError> {
+        Ok(Canonical::Active(package.identifier.clone()))
+    }
     /// Star the package.
-    fn star(&self, logger: &Logger, package: &Package) -> Result<(), BoxedError>;
+    ///
+    /// Non-2xx responses should be reported as errors through [`Error::from_response`].
+    fn star(&self, logger: &Logger, package: &Package) -> Result<StarStatus, Error>;
+}
+
+/// State of a repository on its target.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Canonical {
+    /// The repository is active under the canonical identifier.
+    Active(String),
+    /// The repository is archived, under the canonical identifier.
+    Archived(String),
+    /// The repository doesn't exist anymore.
+    NotFound,
+}
+
+/// Errors of sources and targets, classified so that callers can react to each class.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A command required by the source is not installed.
+    #[error("command not found: {0}")]
+    CommandNotFound(String),
+    /// Malformed output of a command, a file or a response.
+    #[error("parse error: {0}")]
+    Parse(String),
+    /// Failed to talk to the server.
+    #[error("network error: {0}")]
+    Network(String),
+    /// The credential is rejected.
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    /// Too many requests. Retry after the given duration, if known.
+    #[error("rate limited")]
+    RateLimited { retry_after: Option<Duration> },
+    /// The repository doesn't exist, or is not visible to the credential.
+    #[error("repository not found")]
+    NotFound,
+    /// Other unexpected response.
+    #[error("non-2xx response: {status} {body}")]
+    Status { status: StatusCode, body: String },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Error {
+    /// Classify a non-2xx response.
+    ///
+    /// Forges report exhausted rate limits with either 429, or 403 and a zero
+    /// `x-ratelimit-remaining` header.
+    pub fn from_response(resp: Response) -> Self {
+        let status = resp.status();
+        let header = |name: &str| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+            || (status == StatusCode::FORBIDDEN && header("x-ratelimit-remaining") == Some(0));
+        if rate_limited {
+            let retry_after = header("retry-after")
+                .or_else(|| header("x-ratelimit-reset").map(|at| at.saturating_sub(unix_now())))
+                .map(Duration::from_secs);
+            return Self::RateLimited { retry_after };
+        }
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized(resp.text().unwrap_or_default()),
+            StatusCode::NOT_FOUND => Self::NotFound,
+            _ => Self::Status {
+                status,
+                body: resp.text().unwrap_or_default(),
+            },
+        }
+    }
+    /// Whether the credential in use is rejected.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, Self::Unauthorized(_))
+    }
+}
+
+impl From<attohttpc::Error> for Error {
+    fn from(e: attohttpc::Error) -> Self {
+        match e.kind() {
+            attohttpc::ErrorKind::Json(e) => Self::Parse(e.to_string()),
+            _ => Self::Network(e.to_string()),
+        }
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(e: Utf8Error) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+/// Run the command and collect its stdout.
+///
+/// A missing executable is reported as [`Error::CommandNotFound`].
+pub fn run_command(command: &mut Command) -> Result<Vec<u8>, Error> {
+    match command.output() {
+        Ok(output) => Ok(output.stdout),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::CommandNotFound(
+            command.get_program().to_string_lossy().into_owned(),
+        )),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Status of a successful star action.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StarStatus {
+    /// The package is starred by this action.
+    Starred,
+    /// The package has been starred before.
+    AlreadyStarred,
+    /// The target recognizes the package, but has no way to star it.
+    Unsupported,
+}
+
+/// Outcome of an attempt to star a package.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", content = "reason", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The package is starred.
+    Starred,
+    /// The package has been starred before.
+    AlreadyStarred,
+    /// The repository is archived, so it's not starred.
+    Archived,
+    /// The repository doesn't exist anymore.
+    NotFound,
+    /// Failed to star the package.
+    Failed(String),
+    /// The package is not attempted, e.g. its target failed to initialize.
+    Skipped(String),
+    /// The package is filtered out by a rule.
+    Filtered(String),
+}
+
+impl From<StarStatus> for Outcome {
+    fn from(status: StarStatus) -> Self {
+        match status {
+            StarStatus::Starred => Self::Starred,
+            StarStatus::AlreadyStarred => Self::AlreadyStarred,
+            StarStatus::Unsupported => Self::Skipped(String::from("starring not supported")),
+        }
+    }
+}
+
+impl Outcome {
+    /// Short name of the outcome.
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Starred => "starred",
+            Self::AlreadyStarred => "already_starred",
+            Self::Archived => "archived",
+            Self::NotFound => "not_found",
+            Self::Failed(_) => "failed",
+            Self::Skipped(_) => "skipped",
+            Self::Filtered(_) => "filtered",
+        }
+    }
+}
+
+/// Specification of a forge instance, in the form of `name=base_url`.
+///
+/// Used to register self-hosted instances as separate targets.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InstanceSpec {
+    /// Name of the target.
+    pub name: &'static str,
+    /// Base url of the instance, e.g. `https://gitlab.gnome.org`.
+    pub base_url: Url,
+}
+
+impl InstanceSpec {
+    /// Create a spec from trusted parts.
+    ///
+    /// # Panics
+    /// Panics if `base_url` is not a valid url.
+    pub fn new(name: &'static str, base_url: &str) -> Self {
+        Self {
+            name,
+            base_url: Url::parse(base_url).expect("valid base url"),
+        }
+    }
+    /// Url of the given path on the instance, e.g. `api/v4/user`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.as_str().trim_end_matches('/'), path)
+    }
+    /// Path segments of a url on the instance, relative to the base url.
+    ///
+    /// Return `None` if the url is not on the instance.
+    pub fn relative_segments(&self, url: &Url) -> Option<Vec<String>> {
+        let mut forge = normalize(url)?;
+        if Some(forge.host.as_str()) != self.base_url.host_str()
+            || forge.port != self.base_url.port()
+        {
+            return None;
+        }
+        // Instances may be served under a path prefix.
+        let prefix: Vec<_> = self
+            .base_url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .collect();
+        if forge.segments.len() < prefix.len()
+            || forge.segments.iter().zip(&prefix).any(|(a, b)| a != b)
+        {
+            return None;
+        }
+        Some(forge.segments.split_off(prefix.len()))
+    }
+    /// Key of the access token of the instance in the secret store.
+    pub fn token_key(&self) -> String {
+        format!("{}_token", self.name)
+    }
+    /// Ask for an access token of the instance.
+    ///
+    /// `forge` is the kind of the instance, `settings` the path of the page to acquire tokens at,
+    /// and `scopes` a description of the required scopes.
+    pub fn prompt_token(
+        &self,
+        logger: &Logger,
+        forge: &str,
+        settings: &str,
+        scopes: &str,
+    ) -> Option<String> {
+        logger.info(format!(
+            "Please enter your {} token for {}.",
+            forge, self.base_url
+        ));
+        logger.info(format!(
+            "Acquire an access token at {}.",
+            self.url(settings)
+        ));
+        logger.info(format!("{} scopes are required.", scopes));
+        warn_public(logger);
+
+        dialoguer::Password::new()
+            .with_prompt(format!(
+                "{} Please input your {} token",
+                style("?").cyan(),
+                forge
+            ))
+            .interact()
+            .ok()
+    }
+}
+
+impl FromStr for InstanceSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, base_url) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected `name=base_url`, got {}", s))?;
+        let base_url = Url::parse(base_url).map_err(|e| format!("{}: {}", base_url, e))?;
+        if name.is_empty() || base_url.host_str().is_none() {
+            return Err(format!("expected `name=base_url`, got {}", s));
+        }
+        Ok(Self {
+            name: leak_name(name.to_string()),
+            base_url,
+        })
+    }
+}
+
+/// A package found by a source, not yet matched to any target.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// Name of the package.
+    pub name: String,
+    /// Candidate urls of the package (e.g., homepage and repository), in order of preference.
+    pub urls: Vec<Url>,
+    /// Name qualified by its category (e.g., `dev-vcs/git` for portage atoms), if any.
+    pub qualified_name: Option<String>,
+    /// Source this package is discovered from.
+    ///
+    /// Filled in by [`SourceRegistry::aggregate`](crate::registry::SourceRegistry::aggregate).
+    pub source: &'static str,
+}
+
+impl Candidate {
+    pub const fn new(name: String, urls: Vec<Url>) -> Self {
+        Self {
+            name,
+            urls,
+            qualified_name: None,
+            source: "",
+        }
+    }
+    #[must_use]
+    pub fn with_qualified_name(mut self, qualified_name: String) -> Self {
+        self.qualified_name = Some(qualified_name);
+        self
+    }
 }
 
 /// A package with star handler packed in.
-/// Construct one through [`TargetRegistry::pack`](crate::registry::TargetRegistry::pack) method.
+/// Construct one through [`TargetRegistry::try_parse`](crate::registry::TargetRegistry::try_parse) method.
 #[derive(Debug, Clone)]
 pub struct Package {
     /// Name of the package.
     pub name: String,
+    /// Url the identifier is extracted from.
+    pub url: Url,
     /// Identifier of the url.
     pub identifier: String,
     /// Target to star the package.
     pub target: &'static str,
+    /// Source this package is discovered from.
+    ///
+    /// Filled in by [`SourceRegistry::aggregate`](crate::registry::SourceRegistry::aggregate).
+    pub source: &'static str,
 }
 
 impl Package {
-    pub const fn new(name: String, identifier: String, target: &'static str) -> Self {
+    pub const fn new(name: String, url: Url, identifier: String, target: &'static str) -> Self {
         Self {
             name,
+            url,
             identifier,
             target,
+            source: "",
         }
     }
 }
@@ -98,3 +502,57 @@ impl Display for Package {
         write!(f, "{}", self.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use attohttpc::StatusCode;
+
+    use crate::normalize::parse_url;
+    use crate::tests::MockServer;
+
+    use super::{Error, InstanceSpec, HTTP};
+
+    #[test]
+    fn test_instance_spec() {
+        let spec: InstanceSpec = "corp=https://example.com:8443/git/".parse().unwrap();
+        assert_eq!(
+            spec.url("api/v1/user"),
+            "https://example.com:8443/git/api/v1/user"
+        );
+        assert_eq!(spec.token_key(), "corp_token");
+
+        let segments = |url: &str| spec.relative_segments(&parse_url(url).unwrap());
+        assert_eq!(
+            segments("https://example.com:8443/git/team/repo.git"),
+            Some(vec![String::from("team"), String::from("repo.git")])
+        );
+        assert_eq!(segments("https://example.com:8443/team/repo"), None);
+        assert_eq!(segments("https://example.com/git/team/repo"), None);
+        assert_eq!(segments("https://example.org:8443/git/team/repo"), None);
+    }
+
+    #[test]
+    fn test_from_response() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/unauthorized" => (401, String::from("bad credentials")),
+            "/missing" => (404, String::new()),
+            "/limited" => (429, String::new()),
+            _ => (500, String::from("oops")),
+        });
+        let error = |path: &str| {
+            let url = server.base_url.join(path).unwrap();
+            Error::from_response(HTTP.get(url.as_str()).send().unwrap())
+        };
+
+        assert!(error("unauthorized").is_unauthorized());
+        assert!(matches!(error("missing"), Error::NotFound));
+        assert!(matches!(
+            error("limited"),
+            Error::RateLimited { retry_after: None }
+        ));
+        assert!(matches!(
+            error("broken"),
+            Error::Status { status: StatusCode::INTERNAL_SERVER_ERROR, body } if body == "oops"
+        ));
+    }
+}
@@ -0,0 +1,400 @@
+//! Configuration files.
+//!
+//! Settings are read from `config.toml` in the config directory, next to `persist.json`, and then
+//! from `.stars.toml` in the working directory, whose values take precedence. Tables are merged
+//! key by key. Command line flags override both, and switches enabled in files can be turned off
+//! with their `--no-*` counterparts (`--full` for `incremental`).
+//!
+//! There is no concurrency setting: star actions run one at a time, and `[rate-limit]` only
+//! slows them down further.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use toml::Value;
+use url::Url;
+
+use crate::args::Args;
+use crate::cache;
+use crate::common::{base_name, leak_name, project_dirs, BoxedError, InstanceSpec};
+use crate::report::ReportFormat;
+
+/// Name of the project-local configuration file.
+pub const LOCAL_CONFIG: &str = ".stars.toml";
+
+/// Effective configuration of a run.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Do not actually star upstream repositories.
+    pub dry_run: bool,
+    /// Skip packages successfully starred in previous runs.
+    pub incremental: bool,
+    /// Resolve renamed or transferred repositories before starring.
+    pub canonicalize: bool,
+    /// Look up upstream of unrecognized system packages on Repology.
+    pub repology: bool,
+    /// Crawl homepages of unrecognized packages for repository links.
+    pub crawl: bool,
+    /// List packages whose upstream is not recognized by any target.
+    pub unresolved: bool,
+    /// Seconds before cached registry metadata is revalidated.
+    pub cache_ttl: u64,
+    /// OAuth app to log in to github.com with the device flow, which needs one.
+    /// Not used for GitHub Enterprise instances.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_client_id: Option<String>,
+    pub sources: Selection,
+    pub targets: Selection,
+    pub instances: Instances,
+    pub filter: FilterConfig,
+    pub rate_limit: RateLimit,
+    pub report: ReportConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            incremental: false,
+            canonicalize: false,
+            repology: false,
+            crawl: false,
+            unresolved: false,
+            cache_ttl: cache::DEFAULT_TTL.as_secs(),
+            github_client_id: None,
+            sources: Selection::default(),
+            targets: Selection::default(),
+            instances: Instances::default(),
+            filter: FilterConfig::default(),
+            rate_limit: RateLimit::default(),
+            report: ReportConfig::default(),
+        }
+    }
+}
+
+/// Modules to use, by name.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Selection {
+    /// Only use these modules. All modules are used if empty.
+    pub enabled: Vec<String>,
+    /// Never use these modules.
+    pub disabled: Vec<String>,
+}
+
+impl Selection {
+    /// Whether the module is selected.
+    ///
+    /// Other accounts of a target (`target@profile`) are also selected by the target name.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        let matches = |s: &String| s == name || s == base_name(name);
+        (self.enabled.is_empty() || self.enabled.iter().any(matches))
+            && !self.disabled.iter().any(matches)
+    }
+}
+
+/// Self-hosted forge instances, by target name.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Instances {
+    pub github_enterprise: BTreeMap<String, Url>,
+    pub gitlab: BTreeMap<String, Url>,
+    pub gitea: BTreeMap<String, Url>,
+}
+
+impl Instances {
+    /// Specs of the given instances.
+    pub fn specs(instances: &BTreeMap<String, Url>) -> Vec<InstanceSpec> {
+        instances
+            .iter()
+            .map(|(name, base_url)| InstanceSpec {
+                name: leak_name(name.clone()),
+                base_url: base_url.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Filter rules on packages. See [`crate::filter`] for the syntax.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FilterConfig {
+    /// Only star packages matching any of these rules.
+    pub include: Vec<String>,
+    /// Never star packages matching any of these rules.
+    pub exclude: Vec<String>,
+}
+
+/// Throttling of star actions.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RateLimit {
+    /// Minimum interval between star actions on a target, e.g. `500ms`.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub interval: Option<Duration>,
+    /// Intervals of specific targets, overriding `interval`.
+    pub targets: BTreeMap<String, humantime_serde::Serde<Duration>>,
+}
+
+impl RateLimit {
+    /// Interval between star actions on the given target.
+    ///
+    /// Other accounts of a target (`target@profile`) share its setting.
+    pub fn interval(&self, target: &str) -> Option<Duration> {
+        self.targets
+            .get(target)
+            .or_else(|| self.targets.get(base_name(target)))
+            .map(|interval| **interval)
+            .or(self.interval)
+    }
+}
+
+/// Defaults of the run report.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ReportConfig {
+    /// Write a report to this path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Format of the report. Guessed from the extension by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<ReportFormat>,
+}
+
+impl Config {
+    /// Path of the global configuration file.
+    pub fn default_path() -> PathBuf {
+        project_dirs().config_dir().join("config.toml")
+    }
+    /// Load the global and project-local configuration files.
+    pub fn new() -> Result<Self, BoxedError> {
+        Self::from_paths(&[Self::default_path(), PathBuf::from(LOCAL_CONFIG)])
+    }
+    /// Load and merge the given files. Later files take precedence, and missing ones are skipped.
+    pub fn from_paths(paths: &[impl AsRef<Path>]) -> Result<Self, BoxedError> {
+        let mut merged = Value::Table(toml::map::Map::new());
+        for path in paths {
+            let path = path.as_ref();
+            let content = match fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+            };
+            let value = content
+                .parse()
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            merge(&mut merged, value);
+        }
+        Ok(merged.try_into()?)
+    }
+    /// Override values with command line flags.
+    pub fn apply_args(&mut self, args: &Args) {
+        self.dry_run = (self.dry_run || args.dry_run) && !args.no_dry_run;
+        self.incremental = (self.incremental || args.incremental) && !args.full;
+        self.canonicalize = (self.canonicalize || args.canonicalize) && !args.no_canonicalize;
+        self.repology = (self.repology || args.repology) && !args.no_repology;
+        self.crawl = (self.crawl || args.crawl) && !args.no_crawl;
+        self.unresolved = (self.unresolved || args.unresolved) && !args.no_unresolved;
+        if let Some(cache_ttl) = args.cache_ttl {
+            self.cache_ttl = cache_ttl;
+        }
+        if let Some(client_id) = &args.github_client_id {
+            self.github_client_id = Some(client_id.clone());
+        }
+        for (instances, specs) in [
+            (
+                &mut self.instances.github_enterprise,
+                &args.github_enterprise,
+            ),
+            (&mut self.instances.gitlab, &args.gitlab),
+            (&mut self.instances.gitea, &args.gitea),
+        ] {
+            for spec in specs {
+                instances.insert(spec.name.to_string(), spec.base_url.clone());
+            }
+        }
+        self.filter.include.extend(args.include.iter().cloned());
+        self.filter.exclude.extend(args.exclude.iter().cloned());
+        // `--disable` applies to sources and targets alike.
+        self.sources.disabled.extend(args.disable.iter().cloned());
+        self.targets.disabled.extend(args.disable.iter().cloned());
+        if let Some(path) = &args.report {
+            self.report.path = Some(path.clone());
+        }
+        if let Some(format) = args.format {
+            self.report.format = Some(format);
+        }
+    }
+    /// Render the configuration as TOML.
+    pub fn render(&self) -> String {
+        toml::to_string_pretty(self).expect("config is serializable")
+    }
+}
+
+/// Merge `other` into `base`, recursing into tables.
+fn merge(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Table(base), Value::Table(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use argh::FromArgs;
+
+    use crate::args::Args;
+    use crate::report::ReportFormat;
+
+    use super::{Config, Selection};
+
+    #[test]
+    fn test_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("config.toml");
+        let local = dir.path().join(".stars.toml");
+        fs::write(
+            &global,
+            r#"
+            canonicalize = true
+            cache-ttl = 3600
+
+            [targets]
+            disabled = ["gitee"]
+
+            [instances.gitlab]
+            gnome = "https://gitlab.gnome.org"
+
+            [rate-limit]
+            interval = "1s"
+
+            [rate-limit.targets]
+            github = "2s"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            &local,
+            r#"
+            cache-ttl = 60
+
+            [instances.gitlab]
+            kde = "https://invent.kde.org"
+
+            [report]
+            path = "stars.md"
+            format = "md"
+            "#,
+        )
+        .unwrap();
+        let missing = dir.path().join("missing.toml");
+
+        let config = Config::from_paths(&[global, local, missing]).unwrap();
+        assert!(config.canonicalize);
+        assert_eq!(config.cache_ttl, 60);
+        assert!(!config.targets.is_enabled("gitee"));
+        assert!(config.targets.is_enabled("github"));
+        assert_eq!(
+            config.instances.gitlab.keys().collect::<Vec<_>>(),
+            ["gnome", "kde"]
+        );
+        assert_eq!(config.report.format, Some(ReportFormat::Markdown));
+        assert_eq!(
+            config.rate_limit.interval("github@bot"),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            config.rate_limit.interval("gitlab"),
+            Some(Duration::from_secs(1))
+        );
+
+        // The rendered config can be read back.
+        let rendered = dir.path().join("rendered.toml");
+        fs::write(&rendered, config.render()).unwrap();
+        assert_eq!(Config::from_paths(&[rendered]).unwrap(), config);
+    }
+
+    #[test]
+    fn test_selection() {
+        let selection = Selection {
+            enabled: vec![String::from("github"), String::from("gitlab@bot")],
+            disabled: vec![String::from("github@work")],
+        };
+        assert!(selection.is_enabled("github"));
+        assert!(selection.is_enabled("github@bot"));
+        assert!(!selection.is_enabled("github@work"));
+        assert!(selection.is_enabled("gitlab@bot"));
+        assert!(!selection.is_enabled("gitlab"));
+        assert!(!selection.is_enabled("gitea@bot"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "dry-rnu = true").unwrap();
+        assert!(Config::from_paths(&[path]).is_err());
+    }
+
+    #[test]
+    fn test_apply_args() {
+        let mut config = Config {
+            incremental: true,
+            dry_run: true,
+            crawl: true,
+            cache_ttl: 60,
+            ..Config::default()
+        };
+        let args = Args::from_args(
+            &["stars"],
+            &[
+                "--full",
+                "--no-dry-run",
+                "--repology",
+                "--cache-ttl",
+                "10",
+                "--gitea",
+                "codeberg=https://codeberg.example",
+                "--disable",
+                "cargo",
+                "--exclude",
+                "owner:acme",
+                "--format",
+                "csv",
+            ],
+        )
+        .unwrap();
+        config.apply_args(&args);
+
+        assert!(!config.incremental);
+        assert!(!config.dry_run);
+        assert!(config.crawl, "switches set in files are kept");
+        assert!(config.repology);
+        assert_eq!(config.cache_ttl, 10);
+        assert_eq!(
+            config.instances.gitea["codeberg"].as_str(),
+            "https://codeberg.example/"
+        );
+        assert!(!config.sources.is_enabled("cargo"));
+        assert!(!config.targets.is_enabled("cargo"));
+        assert_eq!(config.filter.exclude, ["owner:acme"]);
+        assert_eq!(config.report.format, Some(ReportFormat::Csv));
+    }
+}
@@ -0,0 +1,226 @@
+//! Homepage crawler.
+//!
+//! Many packages have a project website as homepage, which links to the repository somewhere.
+//! This resolver fetches the homepage and looks for such links.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tap::TapFallible;
+use url::Url;
+
+use crate::cache::Cache;
+use crate::common::{BoxedError, Candidate, Resolver, Source};
+use crate::Logger;
+
+static RE_ANCHOR: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?is)<a\s([^>]*)>(.*?)</a>"#).unwrap());
+static RE_HREF: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)\bhref\s*=\s*["']([^"']+)["']"#).unwrap());
+static RE_REL_ME: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)\brel\s*=\s*["'][^"']*\bme\b[^"']*["']"#).unwrap());
+static RE_SOURCE_TEXT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)source|repository|\brepo\b|github|gitlab|codeberg|fork me").unwrap()
+});
+static RE_GO_IMPORT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)<meta\s+name\s*=\s*["']go-import["']\s+content\s*=\s*["']\S+\s+git\s+(\S+?)["']"#,
+    )
+    .unwrap()
+});
+static RE_GIT_CLONE: Lazy<Regex> = Lazy::new(|| {
+    // Skip options until the first url-like argument on the same line.
+    Regex::new(r"git[^\S\n]+clone(?:[^\S\n]+\S+)*?[^\S\n]+((?:https?|git|ssh)://\S+)").unwrap()
+});
+static RE_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+const SCORE_GO_IMPORT: u32 = 100;
+const SCORE_GIT_CLONE: u32 = 80;
+const SCORE_REL_ME: u32 = 60;
+const SCORE_SOURCE_TEXT: u32 = 40;
+const SCORE_LINK: u32 = 1;
+/// Minimum score of an accepted link. Bare links only break ties between links with evidence.
+const MIN_SCORE: u32 = SCORE_SOURCE_TEXT;
+
+/// Resolver crawling homepages for repository links.
+pub struct Crawler {
+    cache: Cache,
+}
+
+impl Crawler {
+    pub const fn new(cache: Cache) -> Self {
+        Self { cache }
+    }
+}
+
+impl Resolver for Crawler {
+    fn name(&self) -> &'static str {
+        "crawler"
+    }
+
+    fn resolve(
+        &self,
+        logger: &Logger,
+        _source: &dyn Source,
+        candidate: &Candidate,
+    ) -> Result<Vec<Url>, BoxedError> {
+        // Sources list the homepage first. Other urls are often release tarballs.
+        let Some(homepage) = candidate
+            .urls
+            .first()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+        else {
+            return Ok(vec![]);
+        };
+
+        logger.set_message(format!("{} (crawling {})", candidate.name, homepage));
+        let page = self
+            .cache
+            .get("homepage", homepage.as_str(), homepage.as_str())
+            .tap_err(|_| {
+                // Remember the failure as an empty page, so that unreachable homepages are not
+                // fetched again until the entry goes stale.
+                self.cache.put("homepage", homepage.as_str(), String::new());
+            })?;
+
+        Ok(scored_links(homepage, &page)
+            .into_iter()
+            .map(|(url, _)| url)
+            .filter(|url| !candidate.urls.contains(url))
+            .collect())
+    }
+}
+
+/// Extract links from the page that may point to the repository, best candidates first.
+///
+/// Links without any evidence (`rel="me"`, source-like text, `go-import` or `git clone`) are
+/// dropped: most of them are footers, social media and the like.
+fn scored_links(base: &Url, page: &str) -> Vec<(Url, u32)> {
+    let mut scores: HashMap<Url, u32> = HashMap::new();
+    let mut add = |link: &str, score: u32| {
+        let link = link.trim_end_matches(['.', ',', ';']);
+        if let Some(url) = normalize_link(base, link) {
+            *scores.entry(url).or_default() += score;
+        }
+    };
+
+    for cap in RE_GO_IMPORT.captures_iter(page) {
+        add(&cap[1], SCORE_GO_IMPORT);
+    }
+    for cap in RE_GIT_CLONE.captures_iter(&RE_TAG.replace_all(page, " ")) {
+        add(&cap[1], SCORE_GIT_CLONE);
+    }
+    for cap in RE_ANCHOR.captures_iter(page) {
+        let (attrs, text) = (&cap[1], &cap[2]);
+        let Some(href) = RE_HREF.captures(attrs) else {
+            continue;
+        };
+        let mut score = SCORE_LINK;
+        if RE_REL_ME.is_match(attrs) {
+            score += SCORE_REL_ME;
+        }
+        if RE_SOURCE_TEXT.is_match(&RE_TAG.replace_all(text, " ")) {
+            score += SCORE_SOURCE_TEXT;
+        }
+        add(&href[1].replace("&amp;", "&"), score);
+    }
+
+    let mut links: Vec<_> = scores
+        .into_iter()
+        .filter(|(_, score)| *score >= MIN_SCORE)
+        .collect();
+    links.sort_by(|(url_a, a), (url_b, b)| b.cmp(a).then_with(|| url_a.cmp(url_b)));
+    links
+}
+
+/// Resolve the link against the page, and drop links unlikely to be useful.
+fn normalize_link(base: &Url, link: &str) -> Option<Url> {
+    let url = base.join(link).ok()?;
+    if !matches!(url.scheme(), "http" | "https" | "git" | "ssh") || url.domain() == base.domain() {
+        return None;
+    }
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+
+    use crate::cache::{Cache, CacheMode};
+    use crate::common::{Candidate, Resolver};
+    use crate::pacman::Pacman;
+    use crate::tests::MockServer;
+    use crate::Logger;
+
+    use super::{scored_links, Crawler};
+
+    #[test]
+    fn test_scored_links() {
+        let base = Url::parse("https://example.org/").unwrap();
+        let page = r#"
+            <meta name="go-import" content="example.org/foo git https://github.com/example/foo">
+            <a href="/docs">Docs</a>
+            <a href="https://twitter.com/example">Twitter</a>
+            <a rel="me" href="https://mastodon.social/@example">Mastodon</a>
+            <a href="https://gitlab.com/example/bar"><img src="x.png"> Source code</a>
+            <pre>$ git clone --depth 1 https://codeberg.org/example/baz.git</pre>
+        "#;
+        let links: Vec<_> = scored_links(&base, page)
+            .into_iter()
+            .map(|(url, _)| url.to_string())
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                "https://github.com/example/foo",
+                "https://codeberg.org/example/baz.git",
+                "https://mastodon.social/@example",
+                "https://gitlab.com/example/bar",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/footer" => (
+                200,
+                String::from(
+                    r#"<p>Foo is a tool.</p>
+                    <footer><a href="https://github.com/someone/hugo-theme">Theme</a> by someone</footer>"#,
+                ),
+            ),
+            _ => (500, String::new()),
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let crawler = Crawler::new(Cache::new(
+            dir.path(),
+            Duration::from_secs(60),
+            CacheMode::Normal,
+        ));
+        let logger = Logger::new(true);
+        let candidate = |path: &str| {
+            Candidate::new(
+                String::from("foo"),
+                vec![server.base_url.join(path).unwrap()],
+            )
+        };
+
+        // A footer link alone is not evidence of the repository.
+        let links = crawler
+            .resolve(&logger, &Pacman, &candidate("footer"))
+            .unwrap();
+        assert!(links.is_empty());
+
+        // Failed crawls are remembered until the entry goes stale.
+        let unreachable = candidate("unreachable");
+        assert!(crawler.resolve(&logger, &Pacman, &unreachable).is_err());
+        assert!(crawler
+            .resolve(&logger, &Pacman, &unreachable)
+            .unwrap()
+            .is_empty());
+        assert_eq!(server.requests().len(), 2);
+    }
+}
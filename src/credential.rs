@@ -0,0 +1,463 @@
+//! Credential lookup.
+//!
+//! Targets don't ask for credentials directly. Instead, they describe where their credentials
+//! may be found, and the first credential found along the chain below is used:
+//!
+//! 1. environment variables, e.g. `GITHUB_TOKEN`, or `STARS_<TARGET>_TOKEN` for any target;
+//! 2. the command line client of the forge, e.g. `gh auth token`;
+//! 3. a token file given by `--token-file target=path`;
+//! 4. credentials saved in a previous run, see [`SecretStore`];
+//! 5. an interactive prompt, only if stdin is a terminal. Credentials entered are saved once
+//!    verified by the target.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use attohttpc::StatusCode;
+
+use crate::common::{BoxedError, Error};
+use crate::secret::SecretStore;
+use crate::Logger;
+
+/// How many times to ask for a credential if the previous one is rejected.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Check whether a credential is accepted by a target.
+pub type Verifier<'a> = &'a dyn Fn(&str) -> Result<(), BoxedError>;
+
+/// Read an environment variable.
+pub type EnvReader = Box<dyn Fn(&str) -> Option<String>>;
+
+/// Where to look for the credential of a target.
+pub struct CredentialSpec<'a> {
+    /// Name of the target.
+    pub target: &'static str,
+    /// Key of the saved credential in the secret store.
+    pub secret_key: String,
+    /// Environment variables holding the credential, in order of preference.
+    pub env: Vec<String>,
+    /// Command printing the credential, e.g. `gh auth token`.
+    pub command: Option<Vec<String>>,
+    /// Ask the user for the credential.
+    pub prompt: &'a dyn Fn(&Logger) -> Option<String>,
+    /// Check whether the credential is accepted by the target.
+    pub verify: Option<Verifier<'a>>,
+}
+
+impl<'a> CredentialSpec<'a> {
+    /// A spec looking up `STARS_<TARGET>_TOKEN` and the saved credential.
+    pub fn new(
+        target: &'static str,
+        secret_key: impl Into<String>,
+        prompt: &'a dyn Fn(&Logger) -> Option<String>,
+    ) -> Self {
+        Self {
+            target,
+            secret_key: secret_key.into(),
+            env: vec![token_var(target)],
+            command: None,
+            prompt,
+            verify: None,
+        }
+    }
+    /// Also look up the given environment variables.
+    pub fn env(mut self, vars: &[&str]) -> Self {
+        self.env.extend(vars.iter().map(ToString::to_string));
+        self
+    }
+    /// Also run the given command, if its program is installed.
+    pub fn command(mut self, command: &[&str]) -> Self {
+        self.command = Some(command.iter().map(ToString::to_string).collect());
+        self
+    }
+    /// The spec of another account on the same target.
+    ///
+    /// Environment variables and commands of the target are not inherited, because they refer to
+    /// the default account.
+    fn for_profile(&self, name: &'static str, profile: &str) -> Self {
+        Self {
+            target: name,
+            secret_key: format!("{}@{}", self.secret_key, profile),
+            env: vec![token_var(name)],
+            command: None,
+            prompt: self.prompt,
+            verify: self.verify,
+        }
+    }
+    /// Verify credentials with the given function.
+    ///
+    /// The function should fail with [`InvalidCredential`], or an [`Error`] of 401 or 403,
+    /// if the credential is rejected.
+    pub fn verify(mut self, verify: Verifier<'a>) -> Self {
+        self.verify = Some(verify);
+        self
+    }
+}
+
+/// The credential is valid, but lacks permissions, e.g. a required scope.
+#[derive(Debug)]
+pub struct InvalidCredential(pub String);
+
+impl Display for InvalidCredential {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidCredential {}
+
+/// Where a credential is found.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Origin {
+    /// Given by the environment, e.g. environment variables or token files.
+    External,
+    /// Saved in a previous run.
+    Saved,
+    /// Entered by the user.
+    Prompt,
+}
+
+/// A token file of a target, in the form of `target=path`.
+#[derive(Debug, Clone)]
+pub struct TokenFile {
+    pub target: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for TokenFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((target, path)) if !target.is_empty() && !path.is_empty() => Ok(Self {
+                target: target.to_string(),
+                path: PathBuf::from(path),
+            }),
+            _ => Err(format!("expected `target=path`, got {}", s)),
+        }
+    }
+}
+
+/// Credential lookup chain shared by all targets.
+pub struct Credentials {
+    token_files: HashMap<String, PathBuf>,
+    secrets: RefCell<SecretStore>,
+    /// Profile being looked up, and the name of the target for the profile.
+    profile: Cell<Option<(&'static str, &'static str)>>,
+    /// Reads environment variables of the process.
+    env: EnvReader,
+    /// Targets whose last credential found was rejected.
+    rejected: RefCell<HashSet<&'static str>>,
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Self::new([], SecretStore::default())
+    }
+}
+
+impl Credentials {
+    pub fn new(token_files: impl IntoIterator<Item = TokenFile>, secrets: SecretStore) -> Self {
+        Self {
+            token_files: token_files
+                .into_iter()
+                .map(|file| (file.target, file.path))
+                .collect(),
+            secrets: RefCell::new(secrets),
+            profile: Cell::new(None),
+            env: Box::new(|var| std::env::var(var).ok()),
+            rejected: RefCell::default(),
+        }
+    }
+
+    /// Whether the last credential found for the target was rejected by it.
+    ///
+    /// Targets without credentials, e.g. because the user can't be asked for one, are not.
+    pub fn rejected(&self, target: &str) -> bool {
+        self.rejected.borrow().contains(target)
+    }
+
+    /// Look up credentials of the given profile instead of the default one within `f`.
+    ///
+    /// `name` is the name of the target for the profile, e.g. `github@bot`.
+    pub fn with_profile<T>(
+        &self,
+        profile: &'static str,
+        name: &'static str,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let prev = self.profile.replace(Some((profile, name)));
+        let output = f();
+        self.profile.set(prev);
+        output
+    }
+
+    /// Look up the credential of a target.
+    ///
+    /// If the spec has a verifier, invalid saved credentials are forgotten, and the user is asked
+    /// again for a few times.
+    /// Returns `None` if no valid credential is found and the user can't be asked for one.
+    pub fn lookup(&self, logger: &Logger, spec: &CredentialSpec) -> Option<String> {
+        let scoped;
+        let spec = if let Some((profile, name)) = self.profile.get() {
+            scoped = spec.for_profile(name, profile);
+            &scoped
+        } else {
+            spec
+        };
+        for _ in 0..MAX_ATTEMPTS {
+            let (value, origin) = self.find(logger, spec)?;
+            match spec.verify.map_or(Ok(()), |verify| verify(&value)) {
+                Ok(()) => {
+                    self.rejected.borrow_mut().remove(spec.target);
+                    if origin == Origin::Prompt {
+                        self.save(logger, spec, &value);
+                    }
+                    return Some(value);
+                }
+                Err(e) if is_invalid(&e) => {
+                    logger.error(format!("Invalid credential for {}: {}", spec.target, e));
+                    self.rejected.borrow_mut().insert(spec.target);
+                    match origin {
+                        // Should be fixed where it's given.
+                        Origin::External => return None,
+                        Origin::Saved => {
+                            logger.warn(format!("Forgetting saved credential for {}", spec.target));
+                            if let Err(e) = self.secrets.borrow_mut().remove(&spec.secret_key) {
+                                logger.warn(format!("Failed to forget credential: {}", e));
+                            }
+                        }
+                        Origin::Prompt => (),
+                    }
+                }
+                Err(e) => {
+                    logger.error(format!(
+                        "Failed to verify credential for {}: {}",
+                        spec.target, e
+                    ));
+                    return None;
+                }
+            }
+        }
+        None
+    }
+
+    /// Find the first credential along the chain.
+    fn find(&self, logger: &Logger, spec: &CredentialSpec) -> Option<(String, Origin)> {
+        if let Some((var, value)) = spec
+            .env
+            .iter()
+            .find_map(|var| Some((var, non_empty((self.env)(var)?)?)))
+        {
+            logger.debug(format!("Using {} credential from ${}", spec.target, var));
+            return Some((value, Origin::External));
+        }
+
+        if let Some(value) = spec.command.as_deref().and_then(run_command) {
+            logger.debug(format!(
+                "Using {} credential from `{}`",
+                spec.target,
+                spec.command.as_deref().unwrap_or_default().join(" ")
+            ));
+            return Some((value, Origin::External));
+        }
+
+        if let Some(path) = self.token_files.get(spec.target) {
+            match fs::read_to_string(path) {
+                Ok(content) => {
+                    if let Some(value) = non_empty(content) {
+                        return Some((value, Origin::External));
+                    }
+                    logger.warn(format!("Token file {} is empty", path.display()));
+                }
+                Err(e) => logger.warn(format!(
+                    "Failed to read token file {}: {}",
+                    path.display(),
+                    e
+                )),
+            }
+        }
+
+        if let Some(value) = self.secrets.borrow().get(&spec.secret_key) {
+            return Some((value.to_string(), Origin::Saved));
+        }
+
+        if !io::stdin().is_terminal() {
+            logger.error(format!(
+                "No credential found for {}, and stdin is not a terminal to ask for one",
+                spec.target
+            ));
+            return None;
+        }
+
+        // Pause progressbar ticking for user input.
+        logger.pause_progress_bar();
+        let value = (spec.prompt)(logger);
+        // Resume progressbar ticking.
+        logger.resume_progress_bar();
+        Some((value?, Origin::Prompt))
+    }
+
+    fn save(&self, logger: &Logger, spec: &CredentialSpec, value: &str) {
+        if let Err(e) = self
+            .secrets
+            .borrow_mut()
+            .insert(spec.secret_key.clone(), value)
+        {
+            logger.warn(format!("Failed to save credential: {}", e));
+        }
+    }
+}
+
+/// Whether the error means the credential is rejected, instead of e.g. a network failure.
+fn is_invalid(e: &BoxedError) -> bool {
+    e.is::<InvalidCredential>()
+        || e.downcast_ref::<Error>().is_some_and(|e| {
+            e.is_unauthorized()
+                || matches!(e, Error::Status { status, .. } if *status == StatusCode::FORBIDDEN)
+        })
+}
+
+/// Environment variable holding the token of a target, e.g. `STARS_GITHUB_BOT_TOKEN`.
+fn token_var(target: &str) -> String {
+    format!(
+        "STARS_{}_TOKEN",
+        target
+            .to_uppercase()
+            .replace(|c: char| !c.is_alphanumeric(), "_")
+    )
+}
+
+/// Run a command and return its trimmed output, if it succeeds.
+fn run_command(command: &[String]) -> Option<String> {
+    let (program, args) = command.split_first()?;
+    let program = which::which(program).ok()?;
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    non_empty(String::from_utf8(output.stdout).ok()?)
+}
+
+fn non_empty(s: String) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::secret::SecretStore;
+    use crate::Logger;
+
+    use super::{CredentialSpec, Credentials, InvalidCredential};
+
+    fn no_prompt(_: &Logger) -> Option<String> {
+        panic!("unexpected prompt")
+    }
+
+    #[test]
+    fn test_chain() {
+        let logger = Logger::new(true);
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = || {
+            let mut secrets = SecretStore::default();
+            secrets.insert("mock_token", "saved").unwrap();
+            secrets
+        };
+
+        // Saved credentials are used if nothing else is given.
+        let credentials = Credentials::new([], secrets());
+        let spec = CredentialSpec::new("mock-target", "mock_token", &no_prompt);
+        assert_eq!(spec.env, vec!["STARS_MOCK_TARGET_TOKEN"]);
+        assert_eq!(credentials.lookup(&logger, &spec).as_deref(), Some("saved"));
+
+        // Token files take precedence over saved credentials.
+        let path = dir.path().join("token");
+        fs::write(&path, "from-file\n").unwrap();
+        let credentials = Credentials::new(
+            [format!("mock-target={}", path.display()).parse().unwrap()],
+            secrets(),
+        );
+        assert_eq!(
+            credentials.lookup(&logger, &spec).as_deref(),
+            Some("from-file")
+        );
+
+        // Commands take precedence over token files.
+        let spec = spec.command(&["echo", "from-command"]);
+        assert_eq!(
+            credentials.lookup(&logger, &spec).as_deref(),
+            Some("from-command")
+        );
+
+        // Environment variables take precedence over everything, in the order given.
+        let mut credentials = credentials;
+        credentials.env = Box::new(|var| match var {
+            "GITHUB_TOKEN" => Some(String::from("from-github-env")),
+            "MOCK_TOKEN" => Some(String::from(" \n")),
+            _ => None,
+        });
+        let spec = spec.env(&["MOCK_TOKEN", "GITHUB_TOKEN"]);
+        assert_eq!(
+            credentials.lookup(&logger, &spec).as_deref(),
+            Some("from-github-env"),
+            "blank variables are skipped"
+        );
+        credentials.env = Box::new(|var| {
+            (var == "STARS_MOCK_TARGET_TOKEN").then(|| String::from("from-stars-env"))
+        });
+        assert_eq!(
+            credentials.lookup(&logger, &spec).as_deref(),
+            Some("from-stars-env")
+        );
+    }
+
+    #[test]
+    fn test_forget_invalid() {
+        let logger = Logger::new(true);
+        let mut secrets = SecretStore::default();
+        secrets.insert("mock_token", "expired").unwrap();
+        let credentials = Credentials::new([], secrets);
+
+        let verify = |token: &str| match token {
+            "expired" => Err(InvalidCredential(String::from("expired")).into()),
+            _ => Ok(()),
+        };
+        let cancel = |_: &Logger| None;
+        let spec = CredentialSpec::new("mock", "mock_token", &cancel).verify(&verify);
+        assert_eq!(credentials.lookup(&logger, &spec), None);
+        assert_eq!(credentials.secrets.borrow().get("mock_token"), None);
+        assert!(credentials.rejected("mock"));
+
+        // Missing credentials are not rejected ones.
+        let spec = CredentialSpec::new("missing", "missing_token", &cancel).verify(&verify);
+        assert_eq!(credentials.lookup(&logger, &spec), None);
+        assert!(!credentials.rejected("missing"));
+    }
+
+    #[test]
+    fn test_profile() {
+        let logger = Logger::new(true);
+        let mut secrets = SecretStore::default();
+        secrets.insert("mock_token", "default").unwrap();
+        secrets.insert("mock_token@bot", "bot").unwrap();
+        let credentials = Credentials::new([], secrets);
+
+        let spec = CredentialSpec::new("mock", "mock_token", &no_prompt);
+        assert_eq!(
+            credentials.lookup(&logger, &spec).as_deref(),
+            Some("default")
+        );
+        let token =
+            credentials.with_profile("bot", "mock@bot", || credentials.lookup(&logger, &spec));
+        assert_eq!(token.as_deref(), Some("bot"));
+    }
+}
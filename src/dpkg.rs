@@ -2,12 +2,11 @@
 use std::collections::HashMap;
 use std::process::Command;
 use std::str;
-use std::str::FromStr;
 
-use url::Url;
-
-use crate::common::{BoxedError, Package, Source, SourceType};
-use crate::{Logger, TargetRegistry};
+use crate::common::{run_command, Candidate, Error, Source, SourceType};
+use crate::normalize::parse_url;
+use crate::repology::{NameType, RepologyRepo};
+use crate::Logger;
 
 #[derive(Debug)]
 pub struct Dpkg;
@@ -29,25 +28,28 @@ impl Source for Dpkg {
         &self,
         _logger: &Logger,
         _files: HashMap<&str, &[u8]>,
-        targets: &TargetRegistry,
-    ) -> Result<Vec<Package>, BoxedError> {
-        let raw_output = Command::new("dpkg-query")
-            .arg("-f")
-            .arg("${source:Package}\t${Homepage}\n")
-            .arg("-W")
-            .output()?
-            .stdout;
-        let output = str::from_utf8(&*raw_output)?;
+    ) -> Result<Vec<Candidate>, Error> {
+        let raw_output = run_command(
+            Command::new("dpkg-query")
+                .arg("-f")
+                .arg("${source:Package}\t${Homepage}\n")
+                .arg("-W"),
+        )?;
+        let output = str::from_utf8(&raw_output)?;
 
         Ok(output
             .lines()
             .filter_map(|line| {
-                line.split_once('\t').and_then(|(name, homepage)| {
-                    targets.try_parse(name.to_string(), &Url::from_str(homepage).ok()?)
+                line.split_once('\t').map(|(name, homepage)| {
+                    Candidate::new(name.to_string(), parse_url(homepage).into_iter().collect())
                 })
             })
             .collect())
     }
+
+    fn repology_repo(&self) -> Option<RepologyRepo> {
+        Some(RepologyRepo::new("debian_unstable", NameType::SrcName))
+    }
 }
 
 #[cfg(test)]
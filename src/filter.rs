@@ -0,0 +1,284 @@
+//! Filters on packages to star.
+//!
+//! A rule is a pattern, optionally prefixed by the field it applies to, e.g. `owner:acme`,
+//! `repo:acme/*-fork` or `name:/^lib.*-sys$/`. Fields are `name`, `repo` (the identifier on the
+//! target), `owner` (the first segment of the identifier), `source` and `target`. Rules without
+//! a field match either the name or the identifier.
+//!
+//! Patterns are globs, or regular expressions if enclosed in slashes. Both are case-insensitive.
+//!
+//! Rules are checked again after canonicalization, so they also apply to the new identifiers of
+//! renamed repositories.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+
+use crate::common::{base_name, BoxedError, Package};
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// Field of a package a rule applies to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Field {
+    /// Either the name or the identifier.
+    Any,
+    Name,
+    Repo,
+    Owner,
+    Source,
+    Target,
+}
+
+impl Field {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "name" => Some(Self::Name),
+            "repo" => Some(Self::Repo),
+            "owner" => Some(Self::Owner),
+            "source" => Some(Self::Source),
+            "target" => Some(Self::Target),
+            _ => None,
+        }
+    }
+
+    fn values(self, package: &Package) -> Vec<&str> {
+        match self {
+            Self::Any => vec![&package.name, &package.identifier],
+            Self::Name => vec![&package.name],
+            Self::Repo => vec![&package.identifier],
+            Self::Owner => vec![package
+                .identifier
+                .split_once('/')
+                .map_or(package.identifier.as_str(), |(owner, _)| owner)],
+            Self::Source => vec![package.source],
+            // Other accounts of a target share its rules.
+            Self::Target => vec![base_name(package.target)],
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Glob(pattern) => pattern.matches_with(value, GLOB_OPTIONS),
+            Self::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// A filter rule, in the form of `[field:]pattern`.
+#[derive(Debug)]
+pub struct Rule {
+    field: Field,
+    matcher: Matcher,
+    raw: String,
+}
+
+impl Rule {
+    /// Whether the package matches the rule.
+    pub fn matches(&self, package: &Package) -> bool {
+        self.field
+            .values(package)
+            .into_iter()
+            .any(|value| self.matcher.matches(value))
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, pattern) = s
+            .split_once(':')
+            .and_then(|(field, pattern)| Some((Field::parse(field)?, pattern)))
+            .unwrap_or((Field::Any, s));
+        let matcher = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(regex) => Matcher::Regex(
+                RegexBuilder::new(regex)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| format!("invalid rule {}: {}", s, e))?,
+            ),
+            None => Matcher::Glob(
+                Pattern::new(pattern).map_err(|e| format!("invalid rule {}: {}", s, e))?,
+            ),
+        };
+        Ok(Self {
+            field,
+            matcher,
+            raw: s.to_string(),
+        })
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// Include and exclude rules.
+///
+/// A package is kept if it matches any include rule (or no include rule is given),
+/// and matches no exclude rule.
+#[derive(Debug, Default)]
+pub struct Filter {
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+}
+
+impl Filter {
+    /// Parse the given rules.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, BoxedError> {
+        let parse = |rules: &[String]| {
+            rules
+                .iter()
+                .map(|rule| rule.parse())
+                .collect::<Result<Vec<Rule>, _>>()
+        };
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+    /// Check whether the package should be starred.
+    ///
+    /// Return the reason if it's filtered out.
+    pub fn check(&self, package: &Package) -> Result<(), String> {
+        if !self.include.is_empty() && !self.include.iter().any(|rule| rule.matches(package)) {
+            return Err(String::from("not included by any rule"));
+        }
+        if let Some(rule) = self.exclude.iter().find(|rule| rule.matches(package)) {
+            return Err(format!("excluded by {}", rule));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::Target;
+    use crate::gitlab::Gitlab;
+    use crate::normalize::parse_url;
+    use crate::tests::package;
+
+    use super::{Filter, Rule};
+
+    #[test]
+    fn test_rule() {
+        let cases = [
+            ("serde*", "serde_json", "serde-rs/json", true),
+            ("rust-lang/*", "cargo", "rust-lang/cargo", true),
+            ("name:rust-lang/*", "cargo", "rust-lang/cargo", false),
+            ("owner:Rust-Lang", "cargo", "rust-lang/cargo", true),
+            ("owner:rust", "cargo", "rust-lang/cargo", false),
+            ("repo:*/json", "serde_json", "serde-rs/json", true),
+            (
+                "name:/^lib.*-sys$/",
+                "libgit2-sys",
+                "rust-lang/git2-rs",
+                true,
+            ),
+            ("name:/^lib.*-sys$/", "git2", "rust-lang/git2-rs", false),
+            ("repo:/^Rust-Lang//", "cargo", "rust-lang/cargo", true),
+            ("source:cargo", "git2", "rust-lang/git2-rs", true),
+            ("target:gitlab", "git2", "rust-lang/git2-rs", false),
+        ];
+        for (rule, name, identifier, expected) in cases {
+            let parsed: Rule = rule.parse().unwrap();
+            assert_eq!(
+                parsed.matches(&package(name, identifier, "cargo")),
+                expected,
+                "{} on {}",
+                rule,
+                identifier
+            );
+        }
+
+        // GitLab projects may be nested in subgroups.
+        let gitlab = Gitlab::new(&Gitlab::known_instances()[0]);
+        let cases = [
+            ("owner:gnome", "https://gitlab.gnome.org/GNOME/gtk", true),
+            ("repo:GNOME/gtk", "https://gitlab.gnome.org/GNOME/gtk", true),
+            (
+                "gnome/*",
+                "https://gitlab.gnome.org/GNOME/gtk/-/issues",
+                true,
+            ),
+            (
+                "owner:World",
+                "https://gitlab.gnome.org/World/design/palette",
+                true,
+            ),
+            (
+                "owner:design",
+                "https://gitlab.gnome.org/World/design/palette",
+                false,
+            ),
+            (
+                "repo:world/*/palette",
+                "https://gitlab.gnome.org/World/design/palette",
+                true,
+            ),
+            (
+                "/^World/design//",
+                "https://gitlab.gnome.org/World/design/palette",
+                true,
+            ),
+        ];
+        for (rule, url, expected) in cases {
+            let identifier = gitlab.try_handle(&parse_url(url).unwrap()).unwrap();
+            let mut package = package("gtk", &identifier, "pacman");
+            package.target = gitlab.name();
+            let parsed: Rule = rule.parse().unwrap();
+            assert_eq!(parsed.matches(&package), expected, "{} on {}", rule, url);
+        }
+
+        let mut package = package("cargo", "rust-lang/cargo", "cargo");
+        package.target = "github@bot";
+        let rule: Rule = "target:github".parse().unwrap();
+        assert!(rule.matches(&package));
+
+        assert!("[".parse::<Rule>().is_err());
+        assert!("name:/(/".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let filter = Filter::new(
+            &[String::from("owner:rust-lang"), String::from("serde*")],
+            &[String::from("name:*-sys")],
+        )
+        .unwrap();
+        assert!(filter
+            .check(&package("cargo", "rust-lang/cargo", "cargo"))
+            .is_ok());
+        assert!(filter
+            .check(&package("serde_json", "serde-rs/json", "cargo"))
+            .is_ok());
+        assert_eq!(
+            filter.check(&package("libc-sys", "rust-lang/libc", "cargo")),
+            Err(String::from("excluded by name:*-sys"))
+        );
+        assert!(filter
+            .check(&package("tokio", "tokio-rs/tokio", "cargo"))
+            .is_err());
+
+        assert!(Filter::default()
+            .check(&package("tokio", "tokio-rs/tokio", "cargo"))
+            .is_ok());
+    }
+}
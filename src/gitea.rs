@@ -0,0 +1,258 @@
+//! Gitea and Forgejo integration.
+
+use attohttpc::header::AUTHORIZATION;
+use attohttpc::StatusCode;
+use itertools::Itertools;
+use serde::Deserialize;
+use url::Url;
+
+use crate::common::{
+    fingerprint, BoxedError, Canonical, Error, InstanceSpec, Package, StarStatus, Target, HTTP,
+};
+use crate::credential::{CredentialSpec, Credentials};
+use crate::normalize::trim_git_suffix;
+use crate::{Logger, Persist};
+
+/// Well-known public Gitea and Forgejo instances.
+const KNOWN_INSTANCES: &[(&str, &str)] = &[("codeberg", "https://codeberg.org")];
+
+/// A Gitea or Forgejo instance.
+pub struct Gitea {
+    instance: InstanceSpec,
+    access_token: Option<String>,
+}
+
+impl Gitea {
+    /// A Gitea instance at the given base url, e.g. `https://codeberg.org`.
+    pub fn new(spec: &InstanceSpec) -> Self {
+        Self {
+            instance: spec.clone(),
+            access_token: None,
+        }
+    }
+    /// Well-known public instances, e.g. Codeberg.
+    pub fn known_instances() -> Vec<InstanceSpec> {
+        KNOWN_INSTANCES
+            .iter()
+            .map(|(name, base_url)| InstanceSpec::new(name, base_url))
+            .collect()
+    }
+
+    fn api(&self, path: &str) -> String {
+        self.instance.url(&format!("api/v1/{}", path))
+    }
+
+    fn auth(&self) -> String {
+        format!("token {}", self.access_token.clone().unwrap())
+    }
+
+    /// Make sure the token is valid.
+    fn verify(&self, token: &str) -> Result<(), BoxedError> {
+        let resp = HTTP
+            .get(self.api("user").as_str())
+            .header(AUTHORIZATION, format!("token {}", token).as_str())
+            .send()?;
+        if !resp.status().is_success() {
+            return Err(Error::from_response(resp).into());
+        }
+        Ok(())
+    }
+}
+
+impl Target for Gitea {
+    fn name(&self) -> &'static str {
+        self.instance.name
+    }
+
+    fn init(&mut self, logger: &Logger, _persist: &mut Persist, credentials: &Credentials) -> bool {
+        let prompt = |logger: &Logger| {
+            self.instance.prompt_token(
+                logger,
+                "Gitea",
+                "user/settings/applications",
+                "`write:user` and `read:repository`",
+            )
+        };
+
+        let verify = |token: &str| self.verify(token);
+        let spec = CredentialSpec::new(self.instance.name, self.instance.token_key(), &prompt)
+            .verify(&verify);
+        let token = credentials.lookup(logger, &spec);
+
+        self.access_token = token;
+        self.access_token.is_some()
+    }
+
+    fn try_handle(&self, url: &Url) -> Option<String> {
+        let segments = self.instance.relative_segments(url)?;
+        let (owner, repo) = segments.iter().take(2).collect_tuple()?;
+        // Gitea paths are case-insensitive.
+        Some(format!("{}/{}", owner, trim_git_suffix(repo)).to_lowercase())
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        self.access_token.as_deref().map(fingerprint)
+    }
+
+    fn canonicalize(&self, _logger: &Logger, package: &Package) -> Result<Canonical, BoxedError> {
+        // Renamed or transferred repos are redirected to their new location.
+        let resp = HTTP
+            .get(self.api(&format!("repos/{}", package.identifier)).as_str())
+            .header(AUTHORIZATION, self.auth().as_str())
+            .send()?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(Canonical::NotFound);
+        }
+        if !resp.status().is_success() {
+            return Err(Error::from_response(resp).into());
+        }
+
+        let repo: Repo = resp.json()?;
+        let identifier = repo.full_name.to_lowercase();
+        Ok(if repo.archived {
+            Canonical::Archived(identifier)
+        } else {
+            Canonical::Active(identifier)
+        })
+    }
+
+    fn star(&self, _logger: &Logger, package: &Package) -> Result<StarStatus, Error> {
+        let url = self.api(&format!("user/starred/{}", package.identifier));
+        let auth = self.auth();
+
+        // `PUT` responds 204 regardless of whether the repo is starred before, so check first.
+        let resp = HTTP
+            .get(url.as_str())
+            .header(AUTHORIZATION, auth.as_str())
+            .send()?;
+        if resp.status() == StatusCode::NO_CONTENT {
+            return Ok(StarStatus::AlreadyStarred);
+        }
+
+        let resp = HTTP
+            .put(url.as_str())
+            .header(AUTHORIZATION, auth.as_str())
+            .send()?;
+
+        if resp.status().is_success() {
+            Ok(StarStatus::Starred)
+        } else {
+            Err(Error::from_response(resp))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Repo {
+    full_name: String,
+    archived: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::common::{Canonical, InstanceSpec, Package, StarStatus, Target};
+    use crate::normalize::parse_url;
+    use crate::tests::MockServer;
+    use crate::Logger;
+
+    use super::Gitea;
+
+    fn mock_instance(server: &MockServer) -> Gitea {
+        let mut gitea = Gitea::new(&InstanceSpec {
+            name: "mock",
+            base_url: server.base_url.clone(),
+        });
+        gitea.access_token = Some(String::from("secret"));
+        gitea
+    }
+
+    fn package(gitea: &Gitea, url: &str) -> Package {
+        let url = Url::parse(url).unwrap();
+        let identifier = gitea.try_handle(&url).unwrap();
+        Package::new(String::from("repo"), url, identifier, gitea.name())
+    }
+
+    #[test]
+    fn test_try_handle() {
+        let codeberg = Gitea::new(&Gitea::known_instances()[0]);
+        let codeberg = &codeberg;
+        let cases = [
+            ("https://codeberg.org/owner/repo", Some("owner/repo")),
+            ("https://codeberg.org/Owner/Repo.git", Some("owner/repo")),
+            ("git@codeberg.org:owner/repo.git", Some("owner/repo")),
+            (
+                "https://codeberg.org/owner/repo/releases/download/v1.0/repo.tar.gz",
+                Some("owner/repo"),
+            ),
+            ("https://codeberg.org/owner", None),
+            ("https://github.com/owner/repo", None),
+        ];
+        for (input, expected) in cases {
+            let url = parse_url(input).unwrap();
+            assert_eq!(codeberg.try_handle(&url).as_deref(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_star() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/api/v1/user/starred/owner/starred") => (204, String::new()),
+            ("GET", _) => (404, String::new()),
+            ("PUT", "/api/v1/user/starred/owner/repo") => (204, String::new()),
+            _ => (403, String::from(r#"{"message":"forbidden"}"#)),
+        });
+        let gitea = mock_instance(&server);
+        let logger = Logger::new(true);
+
+        let url = server.base_url.join("owner/repo").unwrap();
+        let result = gitea.star(&logger, &package(&gitea, url.as_str()));
+        assert_eq!(result.unwrap(), StarStatus::Starred);
+
+        let url = server.base_url.join("owner/starred").unwrap();
+        let result = gitea.star(&logger, &package(&gitea, url.as_str()));
+        assert_eq!(result.unwrap(), StarStatus::AlreadyStarred);
+
+        let url = server.base_url.join("owner/private").unwrap();
+        assert!(gitea.star(&logger, &package(&gitea, url.as_str())).is_err());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        assert!(requests
+            .iter()
+            .all(|req| req.authorization.as_deref() == Some("token secret")));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/api/v1/repos/owner/old" => (
+                200,
+                String::from(r#"{"full_name":"Owner/New","archived":false}"#),
+            ),
+            "/api/v1/repos/owner/archived" => (
+                200,
+                String::from(r#"{"full_name":"owner/archived","archived":true}"#),
+            ),
+            _ => (404, String::new()),
+        });
+        let gitea = mock_instance(&server);
+        let logger = Logger::new(true);
+
+        let cases = [
+            ("owner/old", Canonical::Active(String::from("owner/new"))),
+            (
+                "owner/archived",
+                Canonical::Archived(String::from("owner/archived")),
+            ),
+            ("owner/missing", Canonical::NotFound),
+        ];
+        for (path, expected) in cases {
+            let url = server.base_url.join(path).unwrap();
+            let result = gitea.canonicalize(&logger, &package(&gitea, url.as_str()));
+            assert_eq!(result.unwrap(), expected, "{}", path);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Gitee integration.
+
+use attohttpc::StatusCode;
+use itertools::Itertools;
+use url::Url;
+
+use crate::common::{
+    fingerprint, BoxedError, Error, InstanceSpec, Package, StarStatus, Target, HTTP,
+};
+use crate::credential::{CredentialSpec, Credentials};
+use crate::normalize::trim_git_suffix;
+use crate::{Logger, Persist};
+
+pub struct Gitee {
+    instance: InstanceSpec,
+    access_token: Option<String>,
+}
+
+impl Default for Gitee {
+    fn default() -> Self {
+        Self {
+            instance: InstanceSpec::new("gitee", "https://gitee.com"),
+            access_token: None,
+        }
+    }
+}
+
+impl Gitee {
+    fn api(&self, path: &str) -> String {
+        self.instance.url(&format!("api/v5/{}", path))
+    }
+
+    /// Make sure the token is valid.
+    fn verify(&self, token: &str) -> Result<(), BoxedError> {
+        let resp = HTTP
+            .get(self.api("user").as_str())
+            .param("access_token", token)
+            .send()?;
+        if !resp.status().is_success() {
+            return Err(Error::from_response(resp).into());
+        }
+        Ok(())
+    }
+}
+
+impl Target for Gitee {
+    fn name(&self) -> &'static str {
+        self.instance.name
+    }
+
+    fn init(&mut self, logger: &Logger, _persist: &mut Persist, credentials: &Credentials) -> bool {
+        let prompt = |logger: &Logger| {
+            self.instance.prompt_token(
+                logger,
+                "Gitee",
+                "profile/personal_access_tokens",
+                "`user_info` and `projects`",
+            )
+        };
+
+        let verify = |token: &str| self.verify(token);
+        let spec = CredentialSpec::new(self.instance.name, self.instance.token_key(), &prompt)
+            .verify(&verify);
+        let token = credentials.lookup(logger, &spec);
+
+        self.access_token = token;
+        self.access_token.is_some()
+    }
+
+    fn try_handle(&self, url: &Url) -> Option<String> {
+        let segments = self.instance.relative_segments(url)?;
+        let (owner, repo) = segments.iter().take(2).collect_tuple()?;
+        Some(format!("{}/{}", owner, trim_git_suffix(repo)))
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        self.access_token.as_deref().map(fingerprint)
+    }
+
+    fn star(&self, _logger: &Logger, package: &Package) -> Result<StarStatus, Error> {
+        let url = self.api(&format!("user/starred/{}", package.identifier));
+        let token = self.access_token.clone().unwrap();
+
+        // Gitee takes the token as a parameter instead of a header.
+        // `PUT` responds 204 regardless of whether the repo is starred before, so check first.
+        let resp = HTTP
+            .get(url.as_str())
+            .param("access_token", &token)
+            .send()?;
+        if resp.status() == StatusCode::NO_CONTENT {
+            return Ok(StarStatus::AlreadyStarred);
+        }
+
+        let resp = HTTP
+            .put(url.as_str())
+            .param("access_token", &token)
+            .send()?;
+
+        if resp.status().is_success() {
+            Ok(StarStatus::Starred)
+        } else {
+            Err(Error::from_response(resp))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::common::{InstanceSpec, Package, StarStatus, Target};
+    use crate::normalize::parse_url;
+    use crate::tests::MockServer;
+    use crate::Logger;
+
+    use super::Gitee;
+
+    #[test]
+    fn test_try_handle() {
+        let cases = [
+            ("https://gitee.com/owner/repo", Some("owner/repo")),
+            ("https://gitee.com/owner/repo.git", Some("owner/repo")),
+            ("git@gitee.com:owner/repo.git", Some("owner/repo")),
+            (
+                "https://gitee.com/owner/repo/tree/master/src",
+                Some("owner/repo"),
+            ),
+            ("https://gitee.com/owner", None),
+            ("https://github.com/owner/repo", None),
+        ];
+        for (input, expected) in cases {
+            let url = parse_url(input).unwrap();
+            assert_eq!(
+                Gitee::default().try_handle(&url).as_deref(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_star() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "GET" if req.path.contains("/starred/owner/starred") => (204, String::new()),
+            "GET" => (404, String::new()),
+            _ => (204, String::new()),
+        });
+        let gitee = Gitee {
+            instance: InstanceSpec::new("gitee", server.base_url.as_str()),
+            access_token: Some(String::from("secret")),
+        };
+        let logger = Logger::new(true);
+        let package = |path: &str| {
+            let url: Url = server.base_url.join(path).unwrap();
+            let identifier = gitee.try_handle(&url).unwrap();
+            Package::new(String::from("repo"), url, identifier, gitee.name())
+        };
+
+        let result = gitee.star(&logger, &package("owner/repo"));
+        assert_eq!(result.unwrap(), StarStatus::Starred);
+        let result = gitee.star(&logger, &package("owner/starred"));
+        assert_eq!(result.unwrap(), StarStatus::AlreadyStarred);
+
+        let requests: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|req| (req.method, req.path))
+            .collect();
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    String::from("GET"),
+                    String::from("/api/v5/user/starred/owner/repo?access_token=secret")
+                ),
+                (
+                    String::from("PUT"),
+                    String::from("/api/v5/user/starred/owner/repo?access_token=secret")
+                ),
+                (
+                    String::from("GET"),
+                    String::from("/api/v5/user/starred/owner/starred?access_token=secret")
+                ),
+            ]
+        );
+    }
+}
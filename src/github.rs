@@ -1,104 +1,537 @@
 //! Github integration.
+//!
+//! Instead of asking for a token, stars can log in to github.com with the OAuth device flow.
+//! No OAuth app is shipped, so the flow only runs if one is given with `--github-client-id`.
+//! GitHub Enterprise Server instances always ask for a token.
 
-use attohttpc::header::AUTHORIZATION;
+use std::collections::HashSet;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use attohttpc::header::{ACCEPT, AUTHORIZATION};
+use attohttpc::StatusCode;
 use console::style;
 use itertools::Itertools;
+use once_cell::unsync::OnceCell;
+use serde::Deserialize;
+use serde_json::json;
+use tap::TapFallible;
 use url::Url;
 
-use crate::common::{BoxedError, Package, Target, HTTP};
+use crate::common::{
+    fingerprint, warn_public, BoxedError, Canonical, Error, InstanceSpec, Package, StarStatus,
+    Target, HTTP,
+};
+use crate::credential::{CredentialSpec, Credentials, InvalidCredential};
+use crate::normalize::trim_git_suffix;
 use crate::{Logger, Persist};
 
-#[derive(Default)]
+/// Top-level paths of github.com that are not user or organization names.
+const RESERVED_OWNERS: &[&str] = &[
+    "about",
+    "apps",
+    "collections",
+    "features",
+    "marketplace",
+    "orgs",
+    "settings",
+    "sponsors",
+    "topics",
+    "trending",
+];
+
+/// Scope required to star repositories.
+const REQUIRED_SCOPE: &str = "public_repo";
+
+/// Maximum page size of the REST API.
+const STARRED_PAGE_SIZE: usize = 100;
+
+/// github.com, or a GitHub Enterprise Server instance.
 pub struct Github {
+    /// Name and web url, e.g. `https://github.com`.
+    instance: InstanceSpec,
+    /// REST API base url, e.g. `https://api.github.com`.
+    api_url: Url,
     credential: Option<String>,
+    /// Client id of the OAuth app used to log in with the device flow.
+    client_id: Option<String>,
+    /// Repositories starred by the user, listed on the first star action.
+    starred: OnceCell<HashSet<String>>,
+}
+
+impl Default for Github {
+    fn default() -> Self {
+        Self {
+            instance: InstanceSpec::new("github", "https://github.com"),
+            api_url: Url::parse("https://api.github.com").unwrap(),
+            credential: None,
+            client_id: None,
+            starred: OnceCell::new(),
+        }
+    }
+}
+
+impl Github {
+    /// A GitHub Enterprise Server instance at the given base url, e.g. `https://github.example.com`.
+    ///
+    /// The REST API is served under `/api/v3` of the instance.
+    pub fn enterprise(spec: &InstanceSpec) -> Self {
+        Self {
+            instance: spec.clone(),
+            api_url: Url::parse(&spec.url("api/v3")).expect("valid api url"),
+            credential: None,
+            client_id: None,
+            starred: OnceCell::new(),
+        }
+    }
+    /// Log in with the OAuth device flow of the given OAuth app instead of asking for a token.
+    ///
+    /// Without a client id, the user is asked for a token.
+    #[must_use]
+    pub fn with_client_id(mut self, client_id: Option<String>) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    fn web(&self, path: &str) -> String {
+        self.instance.url(path)
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}/{}", self.api_url.as_str().trim_end_matches('/'), path)
+    }
+
+    fn credential_key(&self) -> String {
+        format!("{}_credential", self.instance.name)
+    }
+
+    fn auth(&self) -> String {
+        bearer(self.credential.as_deref().unwrap())
+    }
+
+    /// Log in with the OAuth device flow, and return the access token.
+    fn device_login(&self, logger: &Logger) -> Result<String, BoxedError> {
+        let client_id = self.client_id.as_deref().ok_or("no OAuth client id")?;
+        let resp = HTTP
+            .post(self.web("login/device/code").as_str())
+            .header(ACCEPT, "application/json")
+            .json(&json!({ "client_id": client_id, "scope": REQUIRED_SCOPE }))?
+            .send()?;
+        if !resp.status().is_success() {
+            return Err(Error::from_response(resp).into());
+        }
+        let code: DeviceCode = resp.json()?;
+
+        logger.info(format!(
+            "Open {} and enter the code {} to authorize stars.",
+            code.verification_uri,
+            style(&code.user_code).bold()
+        ));
+
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval);
+        while Instant::now() < deadline {
+            thread::sleep(interval);
+            let resp: AccessToken = HTTP
+                .post(self.web("login/oauth/access_token").as_str())
+                .header(ACCEPT, "application/json")
+                .json(&json!({
+                    "client_id": client_id,
+                    "device_code": code.device_code,
+                    "grant_type": "urn:ietf:params:oauth:grant-type:device_code",
+                }))?
+                .send()?
+                .json()?;
+            match (resp.access_token, resp.error.as_deref()) {
+                (Some(token), _) => return Ok(token),
+                (None, Some("authorization_pending")) => (),
+                (None, Some("slow_down")) => interval += Duration::from_secs(5),
+                (None, error) => {
+                    return Err(error.unwrap_or("no access token in response").into());
+                }
+            }
+        }
+        Err("device code expired".into())
+    }
+
+    /// Make sure the token is allowed to star repositories.
+    ///
+    /// Fine-grained tokens have no scopes, so they are only checked to be valid.
+    fn verify(&self, credential: &str) -> Result<(), BoxedError> {
+        let resp = HTTP
+            .get(self.api("user").as_str())
+            .header(AUTHORIZATION, bearer(credential).as_str())
+            .send()?;
+        if !resp.status().is_success() {
+            return Err(Error::from_response(resp).into());
+        }
+        if let Some(scopes) = resp.headers().get("x-oauth-scopes") {
+            let scopes = scopes.to_str()?;
+            if !scopes
+                .split(',')
+                .any(|scope| matches!(scope.trim(), "public_repo" | "repo"))
+            {
+                return Err(InvalidCredential(format!(
+                    "`{}` scope is required, got `{}`",
+                    REQUIRED_SCOPE, scopes
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// List repositories starred by the user, by lowercased full name.
+    fn list_starred(&self) -> Result<HashSet<String>, Error> {
+        let mut starred = HashSet::new();
+        for page in 1.. {
+            let resp = HTTP
+                .get(self.api("user/starred").as_str())
+                .header(AUTHORIZATION, self.auth().as_str())
+                .param("per_page", STARRED_PAGE_SIZE)
+                .param("page", page)
+                .send()?;
+            if !resp.status().is_success() {
+                return Err(Error::from_response(resp));
+            }
+            let repos: Vec<Repo> = resp.json()?;
+            let last = repos.len() < STARRED_PAGE_SIZE;
+            starred.extend(repos.into_iter().map(|repo| repo.full_name.to_lowercase()));
+            if last {
+                break;
+            }
+        }
+        Ok(starred)
+    }
 }
 
 impl Target for Github {
     fn name(&self) -> &'static str {
-        "github"
+        self.instance.name
     }
 
-    fn init(&mut self, logger: &Logger, persist: &mut Persist) -> bool {
-        #[allow(clippy::option_if_let_else)] // borrow ck fails
-        // Check for saved credentials.
-        let credential = if let Some(token) =
-            persist.get_state(|state| state.get("github_credential").cloned())
-        {
-            token.as_str().unwrap().to_string()
+    fn init(&mut self, logger: &Logger, _persist: &mut Persist, credentials: &Credentials) -> bool {
+        let prompt = |logger: &Logger| {
+            if self.client_id.is_some() {
+                warn_public(logger);
+                return self
+                    .device_login(logger)
+                    .tap_err(|e| logger.error(format!("GitHub login failed: {}", e)))
+                    .ok();
+            }
+
+            self.instance
+                .prompt_token(logger, "GitHub", "settings/tokens/new", "`public_repo`")
+        };
+
+        let host = self.instance.base_url.host_str().unwrap_or_default();
+        // Same variables as the `gh` cli.
+        let env: &[&str] = if host == "github.com" {
+            &["GH_TOKEN", "GITHUB_TOKEN"]
         } else {
-            // No saved credentials. Ask for one.
-
-            // Pause progressbar ticking for user input.
-            logger.pause_progress_bar();
-
-            // Ask for credentials.
-            logger.info("Please enter your GitHub credentials.");
-            logger
-                .info("Acquire a personal access token at https://github.com/settings/tokens/new.");
-            logger.info("`public_repo` scope is required.");
-            logger.warn("Beware that star actions will be publicly visible.");
-            logger.warn("To avoid polluting your timeline, consider using a dedicated account.");
-
-            let username: String = dialoguer::Input::new()
-                .with_prompt(format!(
-                    "{} Please input your GitHub username",
-                    style("?").cyan()
-                ))
-                .interact()
-                .expect("write to term");
-            let token: String = dialoguer::Password::new()
-                .with_prompt(format!(
-                    "{} Please input your GitHub token",
-                    style("?").cyan()
-                ))
-                .interact()
-                .expect("write to term");
-            let cred = format!("{}:{}", username, token);
-
-            // Save credentials.
-            persist.with_state(|state| {
-                state.insert(String::from("github_credential"), cred.clone().into());
-            });
-
-            // Resume progressbar ticking.
-            logger.resume_progress_bar();
-            cred
+            &["GH_ENTERPRISE_TOKEN", "GITHUB_ENTERPRISE_TOKEN"]
         };
+        let verify = |credential: &str| self.verify(credential);
+        let spec = CredentialSpec::new(self.instance.name, self.credential_key(), &prompt)
+            .env(env)
+            .command(&["gh", "auth", "token", "--hostname", host])
+            .verify(&verify);
+        let credential = credentials.lookup(logger, &spec);
 
-        self.credential = Some(credential);
-        true
+        self.credential = credential;
+        self.credential.is_some()
     }
 
     fn try_handle(&self, url: &Url) -> Option<String> {
-        let domain = url.domain()?;
-        let segments = url.path_segments()?;
-        if domain != "github.com" && domain != "www.github.com" {
+        let segments = self.instance.relative_segments(url)?;
+        let (user, repo) = segments.iter().take(2).collect_tuple()?;
+        if RESERVED_OWNERS.contains(&user.to_lowercase().as_str()) {
             return None;
         }
+        // GitHub paths are case-insensitive.
+        Some(format!("{}/{}", user, trim_git_suffix(repo)).to_lowercase())
+    }
 
-        let (user, repo) = segments.take(2).filter(|s| !s.is_empty()).collect_tuple()?;
-        let repo = repo.trim_end_matches(".git");
-        Some(format!("{}/{}", user, repo))
+    fn fingerprint(&self) -> Option<u64> {
+        self.credential.as_deref().map(fingerprint)
     }
 
-    fn star(&self, logger: &Logger, package: &Package) -> Result<(), BoxedError> {
+    fn canonicalize(&self, _logger: &Logger, package: &Package) -> Result<Canonical, BoxedError> {
+        // Renamed or transferred repos are redirected to their new location.
         let resp = HTTP
-            .put(format!("https://api.github.com/user/starred/{}", package.identifier).as_str())
-            .header(
-                AUTHORIZATION,
-                format!("Basic {}", base64::encode(self.credential.clone().unwrap())).as_str(),
-            )
+            .get(self.api(&format!("repos/{}", package.identifier)).as_str())
+            .header(AUTHORIZATION, self.auth().as_str())
             .send()?;
 
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(Canonical::NotFound);
+        }
         if !resp.status().is_success() {
-            logger.warn(format!(
-                "Non-2xx response for {}: {} {}",
-                package,
-                resp.status(),
-                resp.text().unwrap_or_default()
-            ));
+            return Err(Error::from_response(resp).into());
         }
 
-        Ok(())
+        let repo: Repo = resp.json()?;
+        // Identifiers are lowercased, like those parsed from urls.
+        let identifier = repo.full_name.to_lowercase();
+        Ok(if repo.archived {
+            Canonical::Archived(identifier)
+        } else {
+            Canonical::Active(identifier)
+        })
+    }
+
+    fn star(&self, logger: &Logger, package: &Package) -> Result<StarStatus, Error> {
+        // `PUT` responds 204 regardless of whether the repo is starred before, so list starred
+        // repos once instead of checking each of them.
+        let starred = self.starred.get_or_init(|| {
+            self.list_starred()
+                .tap_err(|e| logger.warn(format!("Failed to list starred repositories: {}", e)))
+                .unwrap_or_default()
+        });
+        if starred.contains(&package.identifier.to_lowercase()) {
+            return Ok(StarStatus::AlreadyStarred);
+        }
+
+        let resp = HTTP
+            .put(
+                self.api(&format!("user/starred/{}", package.identifier))
+                    .as_str(),
+            )
+            .header(AUTHORIZATION, self.auth().as_str())
+            .send()?;
+
+        if resp.status().is_success() {
+            Ok(StarStatus::Starred)
+        } else {
+            Err(Error::from_response(resp))
+        }
+    }
+}
+
+fn bearer(credential: &str) -> String {
+    // Credentials saved by previous versions are `username:token` pairs.
+    let token = credential
+        .split_once(':')
+        .map_or(credential, |(_, token)| token);
+    format!("Bearer {}", token)
+}
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Repo {
+    full_name: String,
+    archived: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::common::{Canonical, Error, Package, StarStatus, Target};
+    use crate::normalize::parse_url;
+    use crate::tests::MockServer;
+    use crate::Logger;
+
+    use super::Github;
+
+    #[test]
+    fn test_try_handle() {
+        let cases = [
+            ("https://github.com/owner/repo", Some("owner/repo")),
+            ("https://GitHub.com/Owner/Repo#readme", Some("owner/repo")),
+            ("git+https://github.com/owner/repo.git", Some("owner/repo")),
+            ("git@github.com:owner/repo.git", Some("owner/repo")),
+            ("https://owner.github.io/repo/", Some("owner/repo")),
+            (
+                "https://raw.githubusercontent.com/owner/repo/HEAD/README.md",
+                Some("owner/repo"),
+            ),
+            (
+                "https://github.com/owner/repo/releases/download/v1.0/repo-1.0.tar.gz",
+                Some("owner/repo"),
+            ),
+            ("https://github.com/owner", None),
+            ("https://github.com/sponsors/owner", None),
+            ("https://gitlab.com/owner/repo", None),
+        ];
+        for (input, expected) in cases {
+            let url: Url = parse_url(input).unwrap();
+            assert_eq!(
+                Github::default().try_handle(&url).as_deref(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_enterprise() {
+        let ghe = Github::enterprise(&"corp=https://github.example.com".parse().unwrap());
+        let url = parse_url("https://github.example.com/Team/Repo.git").unwrap();
+        assert_eq!(ghe.name(), "corp");
+        assert_eq!(ghe.try_handle(&url).as_deref(), Some("team/repo"));
+        assert_eq!(
+            ghe.try_handle(&parse_url("https://github.com/team/repo").unwrap()),
+            None
+        );
+        assert_eq!(
+            Github::default().try_handle(&url),
+            None,
+            "github.com must not claim enterprise urls"
+        );
+        assert_eq!(
+            ghe.api("user/starred/team/repo"),
+            "https://github.example.com/api/v3/user/starred/team/repo"
+        );
+
+        let prefixed = Github::enterprise(&"corp=https://example.com/github/".parse().unwrap());
+        let url = parse_url("https://example.com/github/Team/Repo/issues").unwrap();
+        assert_eq!(prefixed.try_handle(&url).as_deref(), Some("team/repo"));
+        assert_eq!(
+            prefixed.try_handle(&parse_url("https://example.com/team/repo").unwrap()),
+            None
+        );
+        assert_eq!(
+            prefixed.api("user"),
+            "https://example.com/github/api/v3/user"
+        );
+        assert_eq!(
+            prefixed.web("settings/tokens/new"),
+            "https://example.com/github/settings/tokens/new"
+        );
+    }
+
+    #[test]
+    fn test_enterprise_star() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "GET" => (
+                200,
+                String::from(r#"[{"full_name":"Team/Starred","archived":false}]"#),
+            ),
+            _ => (204, String::new()),
+        });
+        let mut ghe = Github::enterprise(&format!("mock={}", server.base_url).parse().unwrap());
+        ghe.credential = Some(String::from("user:secret"));
+
+        let logger = Logger::new(true);
+        let package = |name: &str| {
+            let url = server.base_url.join(&format!("team/{}", name)).unwrap();
+            let identifier = ghe.try_handle(&url).unwrap();
+            Package::new(String::from(name), url, identifier, ghe.name())
+        };
+        assert_eq!(
+            ghe.star(&logger, &package("repo")).unwrap(),
+            StarStatus::Starred
+        );
+        assert_eq!(
+            ghe.star(&logger, &package("starred")).unwrap(),
+            StarStatus::AlreadyStarred
+        );
+
+        let requests = server.requests();
+        assert_eq!(
+            requests
+                .iter()
+                .map(|req| (req.method.as_str(), req.path.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("GET", "/api/v3/user/starred?per_page=100&page=1"),
+                ("PUT", "/api/v3/user/starred/team/repo"),
+            ]
+        );
+        assert_eq!(
+            requests[0].authorization.as_deref(),
+            Some("Bearer secret"),
+            "legacy credentials are sent as bearer tokens"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/api/v3/repos/team/old" => (
+                200,
+                String::from(r#"{"full_name":"Team/New","archived":false}"#),
+            ),
+            "/api/v3/repos/team/legacy" => (
+                200,
+                String::from(r#"{"full_name":"Team/Legacy","archived":true}"#),
+            ),
+            _ => (404, String::new()),
+        });
+        let mut ghe = Github::enterprise(&format!("mock={}", server.base_url).parse().unwrap());
+        ghe.credential = Some(String::from("secret"));
+
+        let logger = Logger::new(true);
+        let canonicalize = |name: &str| {
+            let url = server.base_url.join(&format!("Team/{}", name)).unwrap();
+            let identifier = ghe.try_handle(&url).unwrap();
+            let package = Package::new(String::from(name), url, identifier, ghe.name());
+            ghe.canonicalize(&logger, &package).unwrap()
+        };
+        assert_eq!(
+            canonicalize("old"),
+            Canonical::Active(String::from("team/new"))
+        );
+        assert_eq!(
+            canonicalize("legacy"),
+            Canonical::Archived(String::from("team/legacy"))
+        );
+        assert_eq!(canonicalize("gone"), Canonical::NotFound);
+    }
+
+    #[test]
+    fn test_device_login() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/login/device/code" => (
+                200,
+                String::from(
+                    r#"{"device_code":"dev","user_code":"ABCD-1234","verification_uri":"https://github.com/login/device","expires_in":60,"interval":0}"#,
+                ),
+            ),
+            "/login/oauth/access_token" if req.body.contains(r#""device_code":"dev""#) => {
+                (200, String::from(r#"{"access_token":"gho_token"}"#))
+            }
+            _ => (400, String::from(r#"{"error":"bad_verification_code"}"#)),
+        });
+        let ghe = Github::enterprise(&format!("mock={}", server.base_url).parse().unwrap())
+            .with_client_id(Some(String::from("client")));
+
+        let token = ghe.device_login(&Logger::new(true)).unwrap();
+        assert_eq!(token, "gho_token");
+        assert!(server.requests()[0]
+            .body
+            .contains(r#""scope":"public_repo""#));
+    }
+
+    #[test]
+    fn test_verify() {
+        let server = MockServer::start(|req| match req.authorization.as_deref() {
+            Some("Bearer classic") => (200, String::from("{}")),
+            _ => (401, String::new()),
+        });
+        let ghe = Github::enterprise(&format!("mock={}", server.base_url).parse().unwrap());
+
+        assert!(ghe.verify("classic").is_ok());
+        assert!(ghe.verify("user:classic").is_ok());
+        let e = ghe.verify("bad").unwrap_err();
+        assert!(e
+            .downcast_ref::<Error>()
+            .is_some_and(Error::is_unauthorized));
     }
 }
@@ -2,103 +2,291 @@
 
 use attohttpc::header::AUTHORIZATION;
 use attohttpc::StatusCode;
-use console::style;
-use itertools::Itertools;
+use serde::Deserialize;
 use url::Url;
 
-use crate::common::{BoxedError, Package, Target, HTTP};
+use crate::common::{
+    fingerprint, BoxedError, Canonical, Error, InstanceSpec, Package, StarStatus, Target, HTTP,
+};
+use crate::credential::{CredentialSpec, Credentials, InvalidCredential};
+use crate::normalize::trim_git_suffix;
 use crate::{Logger, Persist};
 
-#[derive(Default)]
+/// Routes that end a project path in legacy urls without the `/-/` separator.
+const ROUTE_KEYWORDS: &[&str] = &[
+    "archive",
+    "blob",
+    "branches",
+    "commit",
+    "commits",
+    "compare",
+    "issues",
+    "merge_requests",
+    "pipelines",
+    "raw",
+    "releases",
+    "tags",
+    "tree",
+    "wikis",
+];
+
+/// Well-known self-hosted GitLab instances.
+const KNOWN_INSTANCES: &[(&str, &str)] = &[
+    ("gitlab-gnome", "https://gitlab.gnome.org"),
+    ("gitlab-freedesktop", "https://gitlab.freedesktop.org"),
+    ("kde-invent", "https://invent.kde.org"),
+    ("debian-salsa", "https://salsa.debian.org"),
+];
+
+/// A GitLab instance, gitlab.com by default.
 pub struct Gitlab {
+    instance: InstanceSpec,
     access_token: Option<String>,
 }
 
-impl Target for Gitlab {
-    fn name(&self) -> &'static str {
-        "gitlab"
+impl Default for Gitlab {
+    fn default() -> Self {
+        Self::new(&InstanceSpec::new("gitlab", "https://gitlab.com"))
     }
+}
 
-    fn init(&mut self, logger: &Logger, persist: &mut Persist) -> bool {
-        #[allow(clippy::option_if_let_else)] // borrow ck fails
-        // Check for saved token.
-        let token = if let Some(token) =
-            persist.get_state(|state| state.get("gitlab_token").cloned())
-        {
-            token.as_str().unwrap().to_string()
-        } else {
-            // No saved token. Ask for one.
-
-            // Pause progressbar ticking for user input.
-            logger.pause_progress_bar();
-
-            // Ask for token.
-            logger.info("Please enter your GitLab token.");
-            logger
-                .info("Acquire a personal access token at https://gitlab.com/-/profile/personal_access_tokens.");
-            logger.info("`api` and `read_api` scopes are required.");
-            logger.warn("Beware that star actions will be publicly visible.");
-            logger.warn("To avoid polluting your timeline, consider using a dedicated account.");
-
-            let token: String = dialoguer::Password::new()
-                .with_prompt(format!(
-                    "{} Please input your GitLab token",
-                    style("?").cyan()
+impl Gitlab {
+    /// A GitLab instance at the given base url, e.g. `https://gitlab.gnome.org`.
+    pub fn new(spec: &InstanceSpec) -> Self {
+        Self {
+            instance: spec.clone(),
+            access_token: None,
+        }
+    }
+    /// Well-known self-hosted GitLab instances.
+    pub fn known_instances() -> Vec<InstanceSpec> {
+        KNOWN_INSTANCES
+            .iter()
+            .map(|(name, base_url)| InstanceSpec::new(name, base_url))
+            .collect()
+    }
+
+    fn api(&self, path: &str) -> String {
+        self.instance.url(&format!("api/v4/{}", path))
+    }
+
+    /// Api url of a project, given by its full path, e.g. `group/sub/project`.
+    fn project_api(&self, path: &str, route: &str) -> String {
+        self.api(&format!("projects/{}{}", urlencoding::encode(path), route))
+    }
+
+    /// Make sure the token is valid and allowed to star projects.
+    fn verify(&self, token: &str) -> Result<(), BoxedError> {
+        let auth = format!("Bearer {}", token);
+        let resp = HTTP
+            .get(self.api("user").as_str())
+            .header(AUTHORIZATION, auth.as_str())
+            .send()?;
+        if !resp.status().is_success() {
+            return Err(Error::from_response(resp).into());
+        }
+
+        // Only personal, group and project access tokens have scopes to check.
+        let resp = HTTP
+            .get(self.api("personal_access_tokens/self").as_str())
+            .header(AUTHORIZATION, auth.as_str())
+            .send()?;
+        if resp.status().is_success() {
+            let token: AccessToken = resp.json()?;
+            if !token.scopes.iter().any(|scope| scope == "api") {
+                return Err(InvalidCredential(format!(
+                    "`api` scope is required, got `{}`",
+                    token.scopes.join(",")
                 ))
-                .interact()
-                .expect("write to term");
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
 
-            // Save token.
-            persist.with_state(|state| {
-                state.insert(String::from("gitlab_token"), token.clone().into());
-            });
+impl Target for Gitlab {
+    fn name(&self) -> &'static str {
+        self.instance.name
+    }
 
-            // Resume progressbar ticking.
-            logger.resume_progress_bar();
-            token
+    fn init(&mut self, logger: &Logger, _persist: &mut Persist, credentials: &Credentials) -> bool {
+        let prompt = |logger: &Logger| {
+            self.instance.prompt_token(
+                logger,
+                "GitLab",
+                "-/profile/personal_access_tokens",
+                "`api` and `read_api`",
+            )
         };
 
-        self.access_token = Some(token);
-        true
+        let host = self.instance.base_url.host_str().unwrap_or_default();
+        let verify = |token: &str| self.verify(token);
+        let mut spec = CredentialSpec::new(self.instance.name, self.instance.token_key(), &prompt)
+            .command(&["glab", "config", "get", "token", "--host", host])
+            .verify(&verify);
+        if host == "gitlab.com" {
+            spec = spec.env(&["GITLAB_TOKEN"]);
+        }
+        let token = credentials.lookup(logger, &spec);
+
+        self.access_token = token;
+        self.access_token.is_some()
     }
 
     fn try_handle(&self, url: &Url) -> Option<String> {
-        let domain = url.domain()?;
-        let segments = url.path_segments()?;
-        if domain != "gitlab.com" && domain != "www.gitlab.com" {
-            return None;
-        }
+        project_path(&self.instance.relative_segments(url)?)
+    }
 
-        let (user, repo) = segments.take(2).filter(|s| !s.is_empty()).collect_tuple()?;
-        let repo = repo.trim_end_matches(".git");
-        let encoded = urlencoding::encode(&format!("{}/{}", user, repo)).to_string();
-        Some(encoded)
+    fn fingerprint(&self) -> Option<u64> {
+        self.access_token.as_deref().map(fingerprint)
     }
 
-    fn star(&self, logger: &Logger, package: &Package) -> Result<(), BoxedError> {
-        let resp = HTTP
-            .post(
-                format!(
-                    "https://gitlab.com/api/v4/projects/{}/star",
-                    package.identifier
+    fn canonicalize(&self, _logger: &Logger, package: &Package) -> Result<Canonical, BoxedError> {
+        // Urls without the `/-/` separator may have extra segments after the project path,
+        // so try shorter paths if the full one is not a project (e.g., it's a group).
+        let segments: Vec<_> = package.identifier.split('/').collect();
+        for len in (2..=segments.len()).rev() {
+            // Renamed or transferred projects are redirected to their new location.
+            let resp = HTTP
+                .get(self.project_api(&segments[..len].join("/"), "").as_str())
+                .header(
+                    AUTHORIZATION,
+                    format!("Bearer {}", self.access_token.clone().unwrap()).as_str(),
                 )
-                .as_str(),
-            )
+                .send()?;
+
+            if resp.status() == StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !resp.status().is_success() {
+                return Err(Error::from_response(resp).into());
+            }
+
+            let project: Project = resp.json()?;
+            return Ok(if project.archived {
+                Canonical::Archived(project.path_with_namespace)
+            } else {
+                Canonical::Active(project.path_with_namespace)
+            });
+        }
+        Ok(Canonical::NotFound)
+    }
+
+    fn star(&self, _logger: &Logger, package: &Package) -> Result<StarStatus, Error> {
+        let resp = HTTP
+            .post(self.project_api(&package.identifier, "/star").as_str())
             .header(
                 AUTHORIZATION,
                 format!("Bearer {}", self.access_token.clone().unwrap()).as_str(),
             )
             .send()?;
 
-        if !resp.status().is_success() && resp.status() != StatusCode::NOT_MODIFIED {
-            logger.warn(format!(
-                "Non-2xx/304 response for {}: {} {}",
-                package,
-                resp.status(),
-                resp.text().unwrap_or_default()
-            ));
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            Ok(StarStatus::AlreadyStarred)
+        } else if resp.status().is_success() {
+            Ok(StarStatus::Starred)
+        } else {
+            Err(Error::from_response(resp))
         }
+    }
+}
 
-        Ok(())
+/// Extract the project path from url path segments.
+///
+/// Projects may be nested in subgroups at any depth. The path ends at the `-` separator
+/// (e.g., `group/sub/project/-/tree/main`), or at a known route for legacy urls.
+fn project_path(segments: &[String]) -> Option<String> {
+    let path: Vec<_> = segments
+        .iter()
+        .enumerate()
+        .take_while(|(idx, s)| *s != "-" && (*idx < 2 || !ROUTE_KEYWORDS.contains(&s.as_str())))
+        .map(|(_, s)| s.as_str())
+        .collect();
+    if path.len() < 2 {
+        return None;
+    }
+    let (project, namespace) = path.split_last()?;
+    Some(format!(
+        "{}/{}",
+        namespace.join("/"),
+        trim_git_suffix(project)
+    ))
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    scopes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Project {
+    path_with_namespace: String,
+    archived: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::Target;
+    use crate::normalize::parse_url;
+
+    use super::Gitlab;
+
+    #[test]
+    fn test_try_handle() {
+        let cases = [
+            ("https://gitlab.com/owner/repo", Some("owner/repo")),
+            ("https://gitlab.com/owner/repo.git", Some("owner/repo")),
+            ("git@gitlab.com:group/sub/repo.git", Some("group/sub/repo")),
+            (
+                "https://gitlab.com/group/sub/subsub/repo",
+                Some("group/sub/subsub/repo"),
+            ),
+            (
+                "https://gitlab.com/group/sub/repo/-/tree/main/src",
+                Some("group/sub/repo"),
+            ),
+            (
+                "https://gitlab.com/group/repo/-/releases",
+                Some("group/repo"),
+            ),
+            (
+                "https://gitlab.com/group/repo/tree/master",
+                Some("group/repo"),
+            ),
+            ("https://gitlab.com/group/tree", Some("group/tree")),
+            ("https://gitlab.com/group", None),
+            ("https://gitlab.com/group/-/issues", None),
+            ("https://github.com/owner/repo", None),
+            ("https://gitlab.gnome.org/GNOME/gtk", None),
+        ];
+        for (input, expected) in cases {
+            let url = parse_url(input).unwrap();
+            let identifier = Gitlab::default().try_handle(&url);
+            assert_eq!(identifier.as_deref(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_instances() {
+        let gnome = Gitlab::new(&Gitlab::known_instances()[0]);
+        let gnome = &gnome;
+        let url = parse_url("https://gitlab.gnome.org/GNOME/gtk/-/issues").unwrap();
+        assert_eq!(gnome.try_handle(&url).as_deref(), Some("GNOME/gtk"));
+        assert_eq!(
+            gnome.project_api("GNOME/gtk", "/star"),
+            "https://gitlab.gnome.org/api/v4/projects/GNOME%2Fgtk/star"
+        );
+
+        let prefixed = Gitlab::new(&"corp=https://example.com/gitlab/".parse().unwrap());
+        let url = parse_url("https://example.com/gitlab/team/repo").unwrap();
+        assert_eq!(prefixed.name(), "corp");
+        assert_eq!(prefixed.try_handle(&url).as_deref(), Some("team/repo"));
+        assert_eq!(
+            prefixed.api("user"),
+            "https://example.com/gitlab/api/v4/user"
+        );
+        let url = parse_url("https://example.com:8443/gitlab/team/repo").unwrap();
+        assert_eq!(prefixed.try_handle(&url), None);
     }
 }
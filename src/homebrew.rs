@@ -1,13 +1,13 @@
 //! Homebrew integration.
 
 use std::collections::HashMap;
+use std::iter;
 use std::process::Command;
 
 use serde::Deserialize;
 use url::Url;
 
-use crate::common::{BoxedError, Package, Source, SourceType};
-use crate::registry::TargetRegistry;
+use crate::common::{run_command, Candidate, Error, Source, SourceType};
 use crate::Logger;
 
 #[derive(Debug)]
@@ -30,41 +30,35 @@ impl Source for Homebrew {
         &self,
         _logger: &Logger,
         _files: HashMap<&str, &[u8]>,
-        targets: &TargetRegistry,
-    ) -> Result<Vec<Package>, BoxedError> {
+    ) -> Result<Vec<Candidate>, Error> {
         // Call `brew info --json=v2 --installed` to get a report of all installed packages.
-        let raw_output = Command::new("brew")
-            .arg("info")
-            .arg("--json=v2")
-            .arg("--installed")
-            .output()?
-            .stdout;
+        let raw_output = run_command(
+            Command::new("brew")
+                .arg("info")
+                .arg("--json=v2")
+                .arg("--installed"),
+        )?;
         // Parse output.
         let output: Output = serde_json::from_slice(&raw_output)?;
 
-        // Extract repositories from formulae.
-        let formulae_iter = output.formulae.into_iter().filter_map(|formula| {
-            targets
-                // Try to extract the repository from homepage first.
-                .try_parse(formula.name.clone(), &formula.homepage)
-                .or_else(|| {
-                    // If failed, extract the repository from the first parsable url.
-                    formula
-                        .urls
-                        .into_iter()
-                        .find_map(|(_, rel)| targets.try_parse(formula.name.clone(), &rel.url))
-                })
+        // Extract urls from formulae.
+        let formulae_iter = output.formulae.into_iter().map(|formula| {
+            Candidate::new(
+                formula.name,
+                // Prefer homepage, then fall back to release urls.
+                iter::once(formula.homepage)
+                    .chain(formula.urls.into_values().map(|rel| rel.url))
+                    .collect(),
+            )
         });
-        // Extract repositories from casks.
-        let casks_iter = output.casks.into_iter().filter_map(|cask| {
-            targets
-                // Try to extract the repository from homepage first.
-                .try_parse(cask.token.clone(), &cask.homepage)
-                // If failed, extract the repository from url.
-                .or_else(|| targets.try_parse(cask.token, &cask.url))
-        });
-
-        // Chain the two iterators together to get a list of all repositories.
+        // Extract urls from casks.
+        let casks_iter = output
+            .casks
+            .into_iter()
+            // Prefer homepage, then fall back to download url.
+            .map(|cask| Candidate::new(cask.token, vec![cask.homepage, cask.url]));
+
+        // Chain the two iterators together to get a list of all packages.
         Ok(formulae_iter.chain(casks_iter).collect())
     }
 }
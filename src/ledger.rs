@@ -0,0 +1,177 @@
+//! Ledger of past runs.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::args::HistoryArgs;
+use crate::common::{project_dirs, unix_now, Outcome, Package};
+use crate::Logger;
+
+/// A record of an attempt to star a package.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// Start time of the run this record belongs to, in unix seconds.
+    pub run: u64,
+    /// Time of the attempt, in unix seconds.
+    pub timestamp: u64,
+    /// Name of the package.
+    pub name: String,
+    /// Source the package is discovered from.
+    pub source: String,
+    /// Target the package is starred on.
+    pub target: String,
+    /// Identifier of the package on its target.
+    pub identifier: String,
+    /// Outcome of the attempt.
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// Append-only ledger recording what was starred, when and why.
+///
+/// Records are stored as json lines in the data directory.
+pub struct Ledger<'a> {
+    run: u64,
+    file: Option<File>,
+    logger: &'a Logger,
+}
+
+impl<'a> Ledger<'a> {
+    /// Open the ledger at default path for appending.
+    pub fn new(logger: &'a Logger) -> Self {
+        Self::from_path(Self::default_path(), logger)
+    }
+    /// Open the ledger at the given path for appending.
+    pub fn from_path(path: impl AsRef<Path>, logger: &'a Logger) -> Self {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                logger.warn(format!("Failed to create data directory: {}", e));
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| logger.warn(format!("Failed to open ledger: {}", e)))
+            .ok();
+        Self {
+            run: unix_now(),
+            file,
+            logger,
+        }
+    }
+    /// Default path of the ledger.
+    pub fn default_path() -> PathBuf {
+        project_dirs().data_dir().join("ledger.jsonl")
+    }
+    /// Append a record of the package to the ledger.
+    pub fn record(&mut self, package: &Package, outcome: &Outcome) {
+        let record = Record {
+            run: self.run,
+            timestamp: unix_now(),
+            name: package.name.clone(),
+            source: package.source.to_string(),
+            target: package.target.to_string(),
+            identifier: package.identifier.clone(),
+            outcome: outcome.clone(),
+        };
+        if let Some(file) = &mut self.file {
+            let mut line = serde_json::to_vec(&record).unwrap();
+            line.push(b'\n');
+            if let Err(e) = file.write_all(&line) {
+                self.logger.warn(format!("Failed to write ledger: {}", e));
+            }
+        }
+    }
+}
+
+/// Read all records from the ledger at the given path.
+///
+/// Malformed lines are skipped.
+pub fn read_records(path: impl AsRef<Path>) -> Vec<Record> {
+    File::open(path).map_or_else(
+        |_| vec![],
+        |file| {
+            BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .collect()
+        },
+    )
+}
+
+/// Print records matching the query.
+pub fn history(args: &HistoryArgs) {
+    let records = read_records(Ledger::default_path());
+    let last_run = records.iter().map(|record| record.run).max();
+
+    let matched: Vec<_> = records
+        .into_iter()
+        .filter(|record| !args.last_run || Some(record.run) == last_run)
+        .filter(|record| args.target.as_ref().is_none_or(|t| &record.target == t))
+        .filter(|record| args.source.as_ref().is_none_or(|s| &record.source == s))
+        .filter(|record| {
+            args.outcome
+                .as_ref()
+                .is_none_or(|o| record.outcome.kind() == o)
+        })
+        .collect();
+    let skip = args
+        .limit
+        .map_or(0, |limit| matched.len().saturating_sub(limit));
+
+    for record in &matched[skip..] {
+        let time =
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(record.timestamp));
+        let reason = match &record.outcome {
+            Outcome::Failed(reason) | Outcome::Skipped(reason) | Outcome::Filtered(reason) => {
+                format!(": {}", reason)
+            }
+            _ => String::new(),
+        };
+        println!(
+            "{} {} {} ({} on {}, from {}){}",
+            time,
+            record.outcome.kind(),
+            record.name,
+            record.identifier,
+            record.target,
+            record.source,
+            reason
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::Outcome;
+    use crate::tests::{package, Fixture};
+
+    use super::{read_records, Ledger};
+
+    #[test]
+    fn test_ledger() {
+        let fixture = Fixture::default();
+        let path = fixture.path("ledger.jsonl");
+
+        let mut ledger = Ledger::from_path(&path, &fixture.logger);
+        let package = package("stars", "PhotonQuantum/stars", "cargo");
+        ledger.record(&package, &Outcome::Starred);
+        ledger.record(&package, &Outcome::Failed(String::from("401 Unauthorized")));
+
+        let records = read_records(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].source, "cargo");
+        assert_eq!(records[0].outcome, Outcome::Starred);
+        assert_eq!(
+            records[1].outcome,
+            Outcome::Failed(String::from("401 Unauthorized"))
+        );
+    }
+}
@@ -4,7 +4,9 @@ use std::fmt::Display;
 use console::{style, Term};
 use indicatif::{ProgressBar, ProgressStyle};
 
+#[derive(Default)]
 enum LogTarget {
+    #[default]
     Plain,
     Progress(
         ProgressBar,
@@ -13,12 +15,6 @@ enum LogTarget {
     ),
 }
 
-impl Default for LogTarget {
-    fn default() -> Self {
-        Self::Plain
-    }
-}
-
 /// Global logger.
 pub struct Logger {
     target: RefCell<LogTarget>,
@@ -87,6 +83,7 @@ impl Logger {
     pub fn set_progress_bar_determinate(&self, max: u64) {
         self.with_progress_bar(|pb| {
             pb.set_length(max);
+            pb.set_position(0);
             pb.set_style(pb_style(0));
             pb.enable_steady_tick(100);
         });
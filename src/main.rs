@@ -5,32 +5,67 @@
 )]
 extern crate core;
 
-use crate::args::Args;
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+use itertools::Itertools;
+
+use crate::args::{Args, Command, ConfigArgs, ConfigCommand};
+use crate::bitbucket::Bitbucket;
+use crate::cache::{Cache, CacheMode};
 use crate::cargo::{Cargo, CargoGlobal};
+use crate::common::{Outcome, Package};
+use crate::config::{Config, Instances};
+use crate::crawler::Crawler;
+use crate::credential::Credentials;
 use crate::dpkg::Dpkg;
+use crate::filter::Filter;
+use crate::gitea::Gitea;
+use crate::gitee::Gitee;
 use crate::github::Github;
 use crate::gitlab::Gitlab;
 use crate::homebrew::Homebrew;
+use crate::ledger::Ledger;
 use crate::logger::Logger;
 use crate::pacman::Pacman;
 use crate::persist::Persist;
 use crate::portage::Portage;
 use crate::registry::{SourceRegistry, TargetRegistry};
+use crate::repology::Repology;
+use crate::report::{Report, ReportFormat, RunStatus};
+use crate::review::Ignored;
+use crate::secret::SecretStore;
+use crate::sourcehut::SourceHut;
 use crate::yum::Yum;
 use crate::zypper::Zypper;
 
 mod args;
+mod bitbucket;
+mod cache;
 mod cargo;
 mod common;
+mod config;
+mod crawler;
+mod credential;
 mod dpkg;
+mod filter;
+mod gitea;
+mod gitee;
 mod github;
 mod gitlab;
 mod homebrew;
+mod ledger;
 mod logger;
+mod normalize;
 mod pacman;
 mod persist;
 mod portage;
 mod registry;
+mod repology;
+mod report;
+mod review;
+mod secret;
+mod sourcehut;
 mod yum;
 mod zypper;
 
@@ -41,7 +76,69 @@ fn main() {
     let args: Args = argh::from_env();
     let logger = Logger::new(args.quiet);
 
+    if let Some(Command::History(history)) = &args.command {
+        ledger::history(history);
+        return;
+    }
+
+    let mut config = match Config::new() {
+        Ok(config) => config,
+        Err(e) => {
+            logger.error(format!("Failed to load config: {}", e));
+            std::process::exit(1);
+        }
+    };
+    config.apply_args(&args);
+    if let Some(Command::Config(ConfigArgs {
+        command: ConfigCommand::Show(_),
+    })) = &args.command
+    {
+        print!("{}", config.render());
+        return;
+    }
+    if args.interactive && !io::stdin().is_terminal() {
+        logger.error("--interactive requires a terminal");
+        std::process::exit(1);
+    }
+    let filter = match Filter::new(&config.filter.include, &config.filter.exclude) {
+        Ok(filter) => filter,
+        Err(e) => {
+            logger.error(format!("Failed to parse filters: {}", e));
+            std::process::exit(1);
+        }
+    };
+
     let mut persist = Persist::new(&logger, args.ignore_saved);
+    let mut secrets = match SecretStore::new(&logger, args.ignore_saved) {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            logger.error(format!("Failed to load secrets: {}", e));
+            std::process::exit(1);
+        }
+    };
+    match secrets.migrate(&mut persist) {
+        Ok(0) => (),
+        Ok(n) => logger.info(format!(
+            "Moved {} saved credentials to the secret store.",
+            n
+        )),
+        Err(e) => logger.warn(format!("Failed to migrate saved credentials: {}", e)),
+    }
+
+    let cache_mode = match (args.offline, args.refresh) {
+        (true, true) => {
+            logger.error("--offline and --refresh can't be used together");
+            std::process::exit(1);
+        }
+        (true, false) => CacheMode::Offline,
+        (false, true) => CacheMode::Refresh,
+        (false, false) => CacheMode::Normal,
+    };
+    let cache = Cache::new(
+        Cache::default_dir(),
+        Duration::from_secs(config.cache_ttl),
+        cache_mode,
+    );
 
     // !! When you implement a new source, you need to add it to the SourceRegistry.
     let mut sources = SourceRegistry::new(&logger);
@@ -50,35 +147,243 @@ fn main() {
     sources.register(Dpkg);
     sources.register(Yum);
     sources.register(Portage);
-    sources.register(Cargo);
-    sources.register(CargoGlobal);
+    sources.register(Cargo::new(cache.clone()));
+    sources.register(CargoGlobal::new(cache.clone()));
     sources.register(Zypper);
 
-    // !! When you implement a new target, you need to add it to the TargetRegistry.
-    let mut targets = TargetRegistry::new(&logger, &mut persist);
-    targets.register(Github::default());
-    targets.register(Gitlab::default());
+    if config.repology {
+        sources.register_resolver(Repology::new(cache.clone()));
+    }
+    if config.crawl {
+        sources.register_resolver(Crawler::new(cache));
+    }
 
-    for disabled in args.disable {
-        sources.deregister(disabled.as_str());
-        targets.deregister(disabled.as_str());
+    // Selected profiles are remembered for later runs, unless this is a dry run.
+    let profiles = if args.profile.is_empty() {
+        persist.get_state(|state| {
+            state
+                .get("profiles")
+                .and_then(|profiles| serde_json::from_value(profiles.clone()).ok())
+                .unwrap_or_default()
+        })
+    } else {
+        if !config.dry_run {
+            persist.with_state(|state| {
+                state.insert(String::from("profiles"), args.profile.clone().into());
+            });
+        }
+        args.profile.clone()
+    };
+
+    let mut ignored = Ignored::load(&persist);
+
+    let mut targets = TargetRegistry::new(&logger, &mut persist);
+    targets.set_profiles(profiles);
+    if let Err(e) = register_targets(&mut targets, &config) {
+        logger.error(format!("Failed to register targets: {}", e));
+        std::process::exit(1);
     }
+    sources.retain(|name| config.sources.is_enabled(name));
+    targets.retain(|name| config.targets.is_enabled(name));
+    targets.set_incremental(config.incremental);
+    targets.set_star_interval(|name| config.rate_limit.interval(name));
+    targets.set_credentials(Credentials::new(args.token_file, secrets));
 
     logger.set_prefix("Aggregating packages...");
-    let packages = sources.aggregate(&targets);
+    let aggregation = sources.aggregate(&targets);
+    let mut packages = aggregation.packages;
+
+    let mut ledger = Ledger::new(&logger);
+    let mut report = Report::default();
+    report.push_unresolved(&aggregation.unresolved);
+    report.push_stats(&aggregation.stats);
+
+    let apply_filter = |packages: &mut Vec<Package>, report: &mut Report| {
+        packages.retain(|package| match filter.check(package) {
+            Ok(()) => true,
+            Err(reason) => {
+                let message = format!("Filtered: {} ({})", package, reason);
+                if config.dry_run {
+                    logger.info(message);
+                } else {
+                    logger.debug(message);
+                }
+                report.push(package, Outcome::Filtered(reason));
+                false
+            }
+        });
+    };
+    apply_filter(&mut packages, &mut report);
+
+    for stats in &aggregation.stats {
+        logger.info(stats);
+    }
+    if config.unresolved {
+        for candidate in &aggregation.unresolved {
+            logger.info(format!(
+                "Unresolved: {} from {} ({})",
+                candidate.name,
+                candidate.source,
+                candidate.urls.iter().join(", ")
+            ));
+        }
+    }
+
+    if config.canonicalize {
+        logger.set_prefix("Resolving repositories...");
+        logger.set_progress_bar_determinate(packages.len() as u64);
+        let (canonical, rejected) = targets.canonicalize(packages);
+        packages = canonical;
+        for (package, outcome) in rejected {
+            if !config.dry_run {
+                ledger.record(&package, &outcome);
+            }
+            report.push(&package, outcome);
+        }
+        // Rules may match the new identifiers of renamed repositories.
+        apply_filter(&mut packages, &mut report);
+    }
+
+    if args.interactive {
+        logger.set_prefix("Reviewing packages...");
+        let selection = match review::review(&logger, &packages, &ignored) {
+            Ok(Some(selection)) => selection,
+            Ok(None) => {
+                logger.warn("Review cancelled, nothing starred.");
+                return;
+            }
+            Err(e) => {
+                logger.error(format!("Failed to review packages: {}", e));
+                std::process::exit(1);
+            }
+        };
+        let (selected, deselected): (Vec<_>, Vec<_>) = packages
+            .into_iter()
+            .zip(selection)
+            .partition(|(_, selected)| *selected);
+        packages = selected.into_iter().map(|(package, _)| package).collect();
+        let deselected: Vec<_> = deselected.into_iter().map(|(package, _)| package).collect();
+        // Like the ledger, the ignore list is left untouched in dry runs.
+        if !config.dry_run {
+            ignored.update(targets.persist(), &packages, &deselected);
+        }
+        for package in &deselected {
+            report.push(
+                package,
+                Outcome::Filtered(String::from("deselected in review")),
+            );
+        }
+    } else {
+        packages.retain(|package| {
+            if ignored.contains(package) {
+                logger.debug(format!("Ignored: {}", package));
+                report.push(
+                    package,
+                    Outcome::Filtered(String::from("ignored in a previous review")),
+                );
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    // Star on every selected account.
+    packages = targets.expand_accounts(packages);
+
+    // Packages handled in previous runs are in the ledger already, so they are only reported.
+    if !config.dry_run {
+        let (pending, handled) = targets.split_handled(packages);
+        packages = pending;
+        for package in &handled {
+            report.push(
+                package,
+                Outcome::Skipped(String::from("handled in a previous run")),
+            );
+        }
+    }
 
     logger.set_prefix("Starring packages...");
     logger.set_progress_bar_determinate(packages.len() as u64);
     for package in &packages {
         logger.set_message(package);
-        if args.dry_run {
+        if config.dry_run {
             logger.debug(format!("Dry-run: star {}, ignored", package));
+            report.push(package, Outcome::Skipped(String::from("dry run")));
         } else {
-            targets.star(package);
+            let outcome = targets.star(package);
+            ledger.record(package, &outcome);
+            report.push(package, outcome);
         }
         logger.with_progress_bar(|pb| pb.inc(1));
     }
     logger.set_plain();
+    targets.save_handled();
+
+    if let Some(path) = &config.report.path {
+        let format = config
+            .report
+            .format
+            .unwrap_or_else(|| ReportFormat::from_path(path));
+        if let Err(e) = report.write(path, format) {
+            logger.error(format!("Failed to write report: {}", e));
+        }
+    }
+
+    let summary = report.summary();
+    for (target, counts) in &summary.targets {
+        logger.info(format!("{}: {}", target, counts));
+    }
+    if summary.unresolved > 0 {
+        logger.info(format!("{} packages unresolved.", summary.unresolved));
+    }
+    let auth_failures = targets.auth_failures();
+    if !auth_failures.is_empty() {
+        logger.error(format!(
+            "Authentication failed on {}.",
+            auth_failures.join(", ")
+        ));
+    }
+
+    let status = summary.status(!auth_failures.is_empty());
+    match status {
+        RunStatus::Success => logger.info("Done!"),
+        RunStatus::PartialFailure => logger.warn("Done, but some packages failed to star."),
+        RunStatus::TotalFailure => logger.error("No package starred, all attempts failed."),
+        RunStatus::AuthFailure => logger.error("Done, but some targets failed to authenticate."),
+    }
+    std::process::exit(status.exit_code());
+}
+
+/// Register built-in targets, followed by self-hosted instances given by the user.
+fn register_targets(targets: &mut TargetRegistry, config: &Config) -> Result<(), String> {
+    // !! When you implement a new target, you need to add it to the TargetRegistry.
+    targets
+        .register_profiled(|| Github::default().with_client_id(config.github_client_id.clone()))?;
+    targets.register_profiled(Gitlab::default)?;
+    for gitlab in Gitlab::known_instances() {
+        targets.register_profiled(|| Gitlab::new(&gitlab))?;
+    }
+    for gitea in Gitea::known_instances() {
+        targets.register_profiled(|| Gitea::new(&gitea))?;
+    }
+    targets.register_profiled(Gitee::default)?;
+    targets.register(SourceHut);
+    targets.register(Bitbucket);
+    register_instances(targets, &config.instances)
+}
 
-    logger.info(format!("Done! {} packages starred.", packages.len()));
+/// Register self-hosted instances given by the user, after built-in targets.
+fn register_instances(targets: &mut TargetRegistry, instances: &Instances) -> Result<(), String> {
+    // GitHub Enterprise has no known instance to override.
+    for spec in &Instances::specs(&instances.github_enterprise) {
+        targets.register_instance(spec, &[], || Github::enterprise(spec))?;
+    }
+    for spec in &Instances::specs(&instances.gitlab) {
+        targets.register_instance(spec, &Gitlab::known_instances(), || Gitlab::new(spec))?;
+    }
+    for spec in &Instances::specs(&instances.gitea) {
+        targets.register_instance(spec, &Gitea::known_instances(), || Gitea::new(spec))?;
+    }
+    Ok(())
 }
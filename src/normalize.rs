@@ -0,0 +1,186 @@
+//! Normalization of forge urls.
+//!
+//! Package metadata refers to repositories in many ways: clone urls, scp-like ssh addresses,
+//! GitHub Pages sites, raw file and release download links. This module reduces them to a host
+//! and a repository path so that targets only need to match the canonical form.
+
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+static RE_SCP: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:[\w.-]+@)?([\w.-]+\.[\w-]+):/?([^/].*)$").unwrap());
+
+/// A location on a forge.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ForgeUrl {
+    /// Lowercase host without `www.` prefix, e.g. `github.com`.
+    pub host: String,
+    /// Port if not the default of the scheme.
+    pub port: Option<u16>,
+    /// Non-empty path segments, e.g. `["owner", "repo.git", "releases"]`.
+    pub segments: Vec<String>,
+}
+
+/// Parse a url, also accepting forms common in package metadata but not valid urls,
+/// e.g. scp-like `git@github.com:owner/repo.git` and `git+https://` urls.
+pub fn parse_url(s: &str) -> Option<Url> {
+    let s = s.trim();
+    if !s.contains("://") {
+        if let Some(cap) = RE_SCP.captures(s) {
+            return Url::parse(&format!("ssh://{}/{}", &cap[1], &cap[2])).ok();
+        }
+    }
+    Url::from_str(s.strip_prefix("git+").unwrap_or(s)).ok()
+}
+
+/// Reduce a url to its location on a forge.
+///
+/// Returns `None` if the url can't refer to a repository (e.g., `mailto:` urls).
+pub fn normalize(url: &Url) -> Option<ForgeUrl> {
+    let scheme = url.scheme().trim_start_matches("git+");
+    if !matches!(scheme, "http" | "https" | "git" | "ssh") {
+        return None;
+    }
+
+    let host = url.host_str()?.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let mut segments: Vec<String> = url
+        .path_segments()?
+        .filter(|s| !s.is_empty())
+        .map(|s| urlencoding::decode(s).map_or_else(|_| s.to_string(), |s| s.into_owned()))
+        .collect();
+
+    let host = match host {
+        // raw.githubusercontent.com/owner/repo/ref/path
+        // codeload.github.com/owner/repo/tar.gz/ref
+        "raw.githubusercontent.com" | "codeload.github.com" => String::from("github.com"),
+        // owner.github.io/repo, or owner.github.io for user sites
+        _ if host.ends_with(".github.io") || host.ends_with(".gitlab.io") => {
+            let (owner, forge) = host.split_once('.')?;
+            let forge = forge.replace(".io", ".com");
+            if segments.is_empty() {
+                segments.push(host.to_string());
+            }
+            segments.insert(0, owner.to_string());
+            forge
+        }
+        _ => host.to_string(),
+    };
+
+    Some(ForgeUrl {
+        host,
+        port: url.port(),
+        segments,
+    })
+}
+
+/// Strip the `.git` suffix of a repository name.
+pub fn trim_git_suffix(repo: &str) -> &str {
+    repo.strip_suffix(".git").unwrap_or(repo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, parse_url};
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("https://github.com/owner/repo", "github.com", "owner/repo"),
+            ("http://github.com/owner/repo", "github.com", "owner/repo"),
+            (
+                "https://www.github.com/owner/repo",
+                "github.com",
+                "owner/repo",
+            ),
+            ("https://GitHub.com/Owner/Repo", "github.com", "Owner/Repo"),
+            (
+                "https://github.com/owner/repo#readme",
+                "github.com",
+                "owner/repo",
+            ),
+            ("https://github.com/owner/repo/", "github.com", "owner/repo"),
+            (
+                "https://github.com/owner/repo.git",
+                "github.com",
+                "owner/repo.git",
+            ),
+            (
+                "git+https://github.com/owner/repo.git",
+                "github.com",
+                "owner/repo.git",
+            ),
+            (
+                "git://github.com/owner/repo.git",
+                "github.com",
+                "owner/repo.git",
+            ),
+            (
+                "ssh://git@github.com/owner/repo.git",
+                "github.com",
+                "owner/repo.git",
+            ),
+            (
+                "git+ssh://git@github.com/owner/repo.git",
+                "github.com",
+                "owner/repo.git",
+            ),
+            (
+                "git@github.com:owner/repo.git",
+                "github.com",
+                "owner/repo.git",
+            ),
+            ("github.com:owner/repo", "github.com", "owner/repo"),
+            ("https://owner.github.io/repo/", "github.com", "owner/repo"),
+            (
+                "https://Owner.GitHub.io/repo/docs/index.html",
+                "github.com",
+                "owner/repo/docs/index.html",
+            ),
+            (
+                "https://owner.github.io",
+                "github.com",
+                "owner/owner.github.io",
+            ),
+            ("https://owner.gitlab.io/repo", "gitlab.com", "owner/repo"),
+            (
+                "https://raw.githubusercontent.com/owner/repo/master/README.md",
+                "github.com",
+                "owner/repo/master/README.md",
+            ),
+            (
+                "https://codeload.github.com/owner/repo/tar.gz/v1.0",
+                "github.com",
+                "owner/repo/tar.gz/v1.0",
+            ),
+            (
+                "https://github.com/owner/repo/releases/download/v1.0/repo-1.0.tar.gz",
+                "github.com",
+                "owner/repo/releases/download/v1.0/repo-1.0.tar.gz",
+            ),
+            (
+                "https://gitlab.com/group/sub/project",
+                "gitlab.com",
+                "group/sub/project",
+            ),
+        ];
+        for (input, host, path) in cases {
+            let url = parse_url(input).unwrap_or_else(|| panic!("failed to parse {}", input));
+            let forge = normalize(&url).unwrap_or_else(|| panic!("failed to normalize {}", input));
+            assert_eq!(forge.host, host, "host of {}", input);
+            assert_eq!(forge.segments.join("/"), path, "path of {}", input);
+        }
+    }
+
+    #[test]
+    fn test_rejected() {
+        for input in ["mailto:owner@example.org", "ftp://ftp.gnu.org/gnu/bash"] {
+            let url = parse_url(input).unwrap();
+            assert_eq!(normalize(&url), None, "{}", input);
+        }
+        assert_eq!(parse_url("not a url"), None);
+    }
+}
@@ -3,12 +3,13 @@ use std::path::{Path, PathBuf};
 
 use serde_json::{Map, Value};
 
+use crate::common::project_dirs;
 use crate::Logger;
 
 /// Store for persist values.
 ///
 /// This is useful when you want to persist values between runs of the program.
-/// E.g., you can use this to store user credentials.
+/// Credentials belong to [`SecretStore`](crate::secret::SecretStore) instead.
 pub struct Persist<'a> {
     kvs: Map<String, Value>,
     path: PathBuf,
@@ -18,10 +19,7 @@ pub struct Persist<'a> {
 impl<'a> Persist<'a> {
     /// Loads the persist values from default path.
     pub fn new(logger: &'a Logger, ignore_exist: bool) -> Self {
-        let config_dir = directories::ProjectDirs::from("me", "lightquantum", "stars")
-            .unwrap()
-            .config_dir()
-            .to_path_buf();
+        let config_dir = project_dirs().config_dir().to_path_buf();
         if let Err(e) = fs::create_dir_all(&config_dir) {
             logger.warn(format!("Failed to create config directory: {}", e));
         }
@@ -35,7 +33,7 @@ impl<'a> Persist<'a> {
     pub fn from_path(path: impl AsRef<Path>, logger: &'a Logger) -> Self {
         let path = path.as_ref();
         let content = fs::read(path).unwrap_or_default();
-        let kvs = serde_json::from_slice(&*content).unwrap_or_default();
+        let kvs = serde_json::from_slice(&content).unwrap_or_default();
         Self {
             kvs,
             path: path.to_path_buf(),
@@ -6,7 +6,6 @@ use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
-use std::str::FromStr;
 use std::{fs, io};
 
 use once_cell::sync::Lazy;
@@ -14,8 +13,10 @@ use regex::Regex;
 use tap::TapFallible;
 use url::Url;
 
-use crate::common::{BoxedError, Package, Source, SourceType};
-use crate::{Logger, TargetRegistry};
+use crate::common::{run_command, Candidate, Error, Source, SourceType};
+use crate::normalize::parse_url;
+use crate::repology::{NameType, RepologyRepo};
+use crate::Logger;
 
 static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?m)^HOMEPAGE="(.+)"$"#).unwrap());
 
@@ -39,31 +40,34 @@ impl Source for Portage {
         &self,
         logger: &Logger,
         _files: HashMap<&str, &[u8]>,
-        targets: &TargetRegistry,
-    ) -> Result<Vec<Package>, BoxedError> {
+    ) -> Result<Vec<Candidate>, Error> {
         let vdb = vdb_path()?;
 
         Ok(iter_atoms(vdb, logger)?
             .into_iter()
-            .filter_map(|atom| {
-                let name =
-                    extract_name_from_fullname(&*atom.fullname.to_string_lossy()).to_string();
+            .map(|atom| {
+                let name = extract_name_from_fullname(&atom.fullname.to_string_lossy()).to_string();
+                // Repology knows gentoo packages by `category/name`, as used in dependency atoms.
+                let qualified_name = format!("{}/{}", atom.category.to_string_lossy(), name);
                 let homepages = homepages(atom.ebuild_path).unwrap_or_else(|e| {
                     logger.warn(format!("atom {:?}: {}", name, e));
                     None
-                })?;
-                homepages
-                    .into_iter()
-                    .find_map(|homepage| targets.try_parse(name.clone(), &homepage))
+                });
+                Candidate::new(name, homepages.unwrap_or_default())
+                    .with_qualified_name(qualified_name)
             })
             .collect())
     }
+
+    fn repology_repo(&self) -> Option<RepologyRepo> {
+        Some(RepologyRepo::new("gentoo", NameType::SrcName))
+    }
 }
 
 /// Get vdb path from portage (normally /var/db/pkg).
-fn vdb_path() -> Result<PathBuf, BoxedError> {
-    let raw_output = Command::new("portageq").arg("vdb_path").output()?;
-    let vdb_path = str::from_utf8(&*raw_output.stdout)?;
+fn vdb_path() -> Result<PathBuf, Error> {
+    let raw_output = run_command(Command::new("portageq").arg("vdb_path"))?;
+    let vdb_path = str::from_utf8(&raw_output)?;
     Ok(PathBuf::from(vdb_path.trim()))
 }
 
@@ -79,12 +83,13 @@ fn extract_name_from_fullname(pf: &str) -> &str {
 }
 
 struct Atom {
+    category: OsString,
     fullname: OsString,
     ebuild_path: OsString,
 }
 
 /// Iterate over all atoms in vdb.
-fn iter_atoms(vdb: impl AsRef<Path>, logger: &Logger) -> Result<Vec<Atom>, BoxedError> {
+fn iter_atoms(vdb: impl AsRef<Path>, logger: &Logger) -> Result<Vec<Atom>, Error> {
     Ok(fs::read_dir(vdb)? // iterate through category dir
         .filter_map(Result::ok)
         .map(|e| e.path())
@@ -100,7 +105,7 @@ fn iter_atoms(vdb: impl AsRef<Path>, logger: &Logger) -> Result<Vec<Atom>, Boxed
                 .into_iter()
                 .flatten()
                 .filter_map(Result::ok)
-                .map(|e| {
+                .map(move |e| {
                     let fullname = e.file_name();
                     // Guess ebuild filename from path.
                     let ebuild_path = {
@@ -110,6 +115,7 @@ fn iter_atoms(vdb: impl AsRef<Path>, logger: &Logger) -> Result<Vec<Atom>, Boxed
                     };
 
                     Atom {
+                        category: category.file_name().unwrap_or_default().to_os_string(),
                         fullname,
                         ebuild_path,
                     }
@@ -126,7 +132,7 @@ fn homepages(ebuild_path: impl AsRef<Path>) -> Result<Option<Vec<Url>>, io::Erro
             .map(|cap| {
                 cap[1]
                     .split(' ')
-                    .map(|homepage| Url::from_str(homepage).ok())
+                    .map(parse_url)
                     .collect::<Option<Vec<Url>>>()
             })
             .unwrap_or_default()),
@@ -1,22 +1,62 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::fs;
+use std::iter;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use itertools::Itertools;
+use serde_json::{json, Value};
 use tap::TapFallible;
 use url::Url;
 
-use crate::common::{Package, Source, SourceType, Target};
+use crate::common::{
+    base_name, leak_name, BoxedError, Candidate, Canonical, Error, InstanceSpec, Outcome, Package,
+    Resolver, Source, SourceType, StarStatus, Target,
+};
+use crate::credential::Credentials;
 use crate::{Logger, Persist};
 
+/// Profile of the account a target is registered with by default.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Stop starring to a target after this many consecutive 401 responses.
+const MAX_UNAUTHORIZED: usize = 3;
+
+/// Retry a rate limited star action at most this many times.
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+/// Wait this long if the target doesn't tell when to retry.
+const DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+/// Never wait longer than this for a rate limit to reset.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(15 * 60);
+
 enum TargetState {
     Uninitialized,
     Initialized,
     Failed,
+    /// The credential is rejected repeatedly after initialization.
+    Aborted,
 }
 
 /// Registry for targets.
 pub struct TargetRegistry<'a> {
     targets: HashMap<&'static str, (Box<dyn Target>, TargetState)>,
+    /// Names of targets in registration order, which is the order urls are matched in.
+    order: Vec<&'static str>,
+    /// Identifiers successfully handled on each initialized target, including previous runs.
+    handled: HashMap<&'static str, HashSet<String>>,
+    /// Consecutive 401 responses of each target.
+    unauthorized: HashMap<&'static str, usize>,
+    incremental: bool,
+    credentials: Credentials,
+    /// Account profiles to star with, the default account only if empty.
+    profiles: Vec<&'static str>,
+    /// Targets of other accounts, keyed by the target of the first account.
+    accounts: HashMap<&'static str, Vec<&'static str>>,
+    /// Minimum interval between star actions on each target.
+    intervals: HashMap<&'static str, Duration>,
+    /// Time of the last star action on each target.
+    last_star: HashMap<&'static str, Instant>,
     logger: &'a Logger,
     persist: &'a mut Persist<'a>,
 }
@@ -26,74 +66,514 @@ impl<'a> TargetRegistry<'a> {
     pub fn new(logger: &'a Logger, persist: &'a mut Persist<'a>) -> Self {
         Self {
             targets: Default::default(),
+            order: vec![],
+            handled: Default::default(),
+            unauthorized: Default::default(),
+            incremental: false,
+            credentials: Credentials::default(),
+            profiles: vec![],
+            accounts: Default::default(),
+            intervals: Default::default(),
+            last_star: Default::default(),
             logger,
             persist,
         }
     }
+    /// Skip packages successfully handled in previous runs, see [`Self::split_handled`].
+    pub fn set_incremental(&mut self, incremental: bool) {
+        self.incremental = incremental;
+    }
+    /// Set the credential lookup chain used by targets.
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = credentials;
+    }
+    /// Wait at least the given interval between star actions on each registered target.
+    ///
+    /// Must be called after registering targets.
+    pub fn set_star_interval(&mut self, interval: impl Fn(&str) -> Option<Duration>) {
+        self.intervals = self
+            .targets
+            .keys()
+            .filter_map(|name| Some((*name, interval(name)?)))
+            .collect();
+    }
+    /// Star with the given account profiles on targets registered by [`Self::register_profiled`].
+    ///
+    /// Must be called before registering targets.
+    pub fn set_profiles(&mut self, profiles: Vec<String>) {
+        self.profiles = profiles.into_iter().unique().map(leak_name).collect();
+    }
+    /// Register a target once per account profile.
+    ///
+    /// The default profile keeps the name of the target, while others are registered as
+    /// separate targets named `target@profile`.
+    ///
+    /// Fails if any account of another target already goes by the same name.
+    pub fn register_profiled<T: Target>(&mut self, make: impl Fn() -> T) -> Result<(), String> {
+        let target = make();
+        if self.is_taken(target.name()) {
+            return Err(format!("target collision: {}", target.name()));
+        }
+        if self.profiles.is_empty() {
+            self.register(target);
+            return Ok(());
+        }
+
+        let mut names = vec![];
+        for profile in self.profiles.clone() {
+            let target = make();
+            if profile == DEFAULT_PROFILE {
+                names.push(target.name());
+                self.register(target);
+            } else {
+                let name = leak_name(format!("{}@{}", target.name(), profile));
+                names.push(name);
+                self.register(Profiled {
+                    inner: target,
+                    name,
+                    profile,
+                });
+            }
+        }
+        if let Some((first, others)) = names.split_first() {
+            self.accounts.insert(first, others.to_vec());
+        }
+        Ok(())
+    }
     /// Register a target.
     pub fn register(&mut self, target: impl Target) {
-        if let Some((collided, _)) = self.targets.insert(
-            target.name(),
-            (Box::new(target), TargetState::Uninitialized),
-        ) {
+        let name = target.name();
+        if let Some((collided, _)) = self
+            .targets
+            .insert(name, (Box::new(target), TargetState::Uninitialized))
+        {
             panic!("target collision: {}", collided.name());
         }
+        self.order.push(name);
+    }
+    /// Register a user-specified instance of a forge with [`Self::register_profiled`].
+    ///
+    /// The instance may override a known instance of the same forge, but no other target.
+    pub fn register_instance<T: Target>(
+        &mut self,
+        spec: &InstanceSpec,
+        known: &[InstanceSpec],
+        make: impl Fn() -> T,
+    ) -> Result<(), String> {
+        if known.iter().any(|known| known.name == spec.name) {
+            self.deregister(spec.name);
+        } else if self.is_taken(spec.name) {
+            return Err(format!(
+                "instance name `{}` is taken by another target",
+                spec.name
+            ));
+        }
+        self.register_profiled(make)
     }
-    /// Deregister a target.
+    /// Deregister a target along with its other accounts.
+    ///
+    /// Profiled targets may be given by their base name, whichever profile comes first.
     pub fn deregister(&mut self, name: &str) -> bool {
-        self.targets.remove(name).is_some()
+        let first = self.targets.keys().copied().find(|target| {
+            (*target == name || base_name(target) == name) && !self.is_secondary(target)
+        });
+        let Some(first) = first else {
+            return false;
+        };
+        let others = self.accounts.remove(first).unwrap_or_default();
+        for other in &others {
+            self.targets.remove(other);
+        }
+        self.order
+            .retain(|target| *target != first && !others.contains(target));
+        self.targets.remove(first).is_some()
+    }
+    /// Whether any account of a registered target goes by the given base name.
+    fn is_taken(&self, name: &str) -> bool {
+        self.targets.keys().any(|target| base_name(target) == name)
+    }
+    /// Whether the target is another account of a profiled target.
+    fn is_secondary(&self, name: &str) -> bool {
+        self.accounts.values().flatten().any(|other| *other == name)
+    }
+
+    /// Persist store shared with targets.
+    pub fn persist(&mut self) -> &mut Persist<'a> {
+        self.persist
+    }
+
+    /// Deregister targets, along with their other accounts, for which `f` returns `false`.
+    pub fn retain(&mut self, f: impl Fn(&str) -> bool) {
+        let rejected: Vec<_> = self
+            .targets
+            .keys()
+            .filter(|name| !self.is_secondary(name))
+            .filter(|name| !f(name))
+            .copied()
+            .collect();
+        for name in rejected {
+            self.deregister(name);
+        }
     }
 
     /// Try to parse a URL into a package recognized by a target.
     pub fn try_parse(&self, name: String, url: &Url) -> Option<Package> {
-        self.targets
+        self.order
             .iter()
-            .find_map(|(target_id, (target, _))| Some((target_id, target.try_handle(url)?)))
-            .map(|(target_id, package_id)| Package::new(name, package_id, target_id))
+            // Packages are handed to other accounts later.
+            .filter(|target_id| !self.is_secondary(target_id))
+            .find_map(|&target_id| {
+                let (target, _) = &self.targets[target_id];
+                Some((target_id, target.try_handle(url)?))
+            })
+            .map(|(target_id, package_id)| Package::new(name, url.clone(), package_id, target_id))
+    }
+
+    /// Try to match a candidate into a package recognized by a target.
+    ///
+    /// Urls are tried in order and the first recognized one wins.
+    pub fn resolve(&self, candidate: &Candidate) -> Option<Package> {
+        candidate
+            .urls
+            .iter()
+            .find_map(|url| self.try_parse(candidate.name.clone(), url))
+            .map(|package| Package {
+                source: candidate.source,
+                ..package
+            })
+    }
+
+    /// Duplicate packages for every other account on their target.
+    pub fn expand_accounts(&self, packages: Vec<Package>) -> Vec<Package> {
+        packages
+            .into_iter()
+            .flat_map(|package| {
+                let others = self.accounts.get(package.target).cloned();
+                let copies: Vec<_> = others
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|target| Package {
+                        target,
+                        ..package.clone()
+                    })
+                    .collect();
+                iter::once(package).chain(copies)
+            })
+            .collect()
+    }
+
+    /// Split off packages successfully handled in previous runs if the registry is incremental.
+    ///
+    /// Targets of the packages are initialized to load what they handled before. Return the
+    /// packages left to star, and those handled.
+    pub fn split_handled(&mut self, packages: Vec<Package>) -> (Vec<Package>, Vec<Package>) {
+        if !self.incremental {
+            return (packages, vec![]);
+        }
+        packages.into_iter().partition(|package| {
+            self.init_target(package.target) != Some(true)
+                || !self.handled[package.target].contains(&package.identifier)
+        })
     }
 
     /// Star a package.
     ///
     /// This will attempt to star the package on its specified target.
     /// If the target is not initialized, it will be initialized.
-    pub fn star(&mut self, package: &Package) {
-        if let Some((target, state)) = self.targets.get_mut(&package.target) {
-            loop {
-                match state {
-                    TargetState::Uninitialized => {
-                        *state = if target.init(self.logger, self.persist) {
-                            TargetState::Initialized
-                        } else {
-                            TargetState::Failed
-                        };
+    /// Targets rejecting the credential repeatedly are aborted, and their packages are skipped.
+    pub fn star(&mut self, package: &Package) -> Outcome {
+        if let Some((_, TargetState::Aborted)) = self.targets.get(package.target) {
+            return Outcome::Skipped(format!(
+                "target {} aborted after repeated authentication failures",
+                package.target
+            ));
+        }
+        match self.init_target(package.target) {
+            None => {
+                self.logger
+                    .error(format!("no such target found: {}", package.target));
+                Outcome::Failed(format!("no such target found: {}", package.target))
+            }
+            Some(false) => {
+                self.logger.warn(format!(
+                    "target {} not loaded, skipped {}",
+                    package.target, package
+                ));
+                Outcome::Skipped(format!("target {} not loaded", package.target))
+            }
+            Some(true) => {
+                let (target, _) = &self.targets[package.target];
+                let handled = self.handled.entry(package.target).or_default();
+                let mut retries = 0;
+                let result = loop {
+                    if let Some(interval) = self.intervals.get(package.target) {
+                        if let Some(last) = self.last_star.get(package.target) {
+                            if let Some(remaining) = interval.checked_sub(last.elapsed()) {
+                                thread::sleep(remaining);
+                            }
+                        }
+                        self.last_star.insert(package.target, Instant::now());
+                    }
+                    match target.star(self.logger, package) {
+                        Err(Error::RateLimited { retry_after })
+                            if retries < MAX_RATE_LIMIT_RETRIES =>
+                        {
+                            retries += 1;
+                            let wait = retry_after
+                                .unwrap_or(DEFAULT_RATE_LIMIT_WAIT)
+                                .min(MAX_RATE_LIMIT_WAIT);
+                            self.logger.warn(format!(
+                                "rate limited by {}, retrying in {}",
+                                package.target,
+                                humantime::format_duration(wait)
+                            ));
+                            thread::sleep(wait);
+                        }
+                        result => break result,
+                    }
+                };
+                match result {
+                    Ok(status) => {
+                        // Keep unsupported packages around in case the target learns to star.
+                        if status != StarStatus::Unsupported {
+                            handled.insert(package.identifier.clone());
+                        }
+                        self.unauthorized.remove(package.target);
+                        status.into()
+                    }
+                    Err(Error::NotFound) => {
+                        self.logger
+                            .warn(format!("repository of {} not found", package));
+                        self.unauthorized.remove(package.target);
+                        Outcome::NotFound
                     }
-                    TargetState::Initialized => {
-                        if let Err(e) = target.star(self.logger, package) {
-                            self.logger
-                                .error(format!("error while starring {}: {}", package, e));
+                    Err(e) => {
+                        self.logger
+                            .error(format!("error while starring {}: {}", package, e));
+                        if e.is_unauthorized() {
+                            self.count_unauthorized(package.target);
                         }
-                        break;
+                        Outcome::Failed(e.to_string())
                     }
-                    TargetState::Failed => {
-                        self.logger.warn(format!(
-                            "target {} not loaded, skipped {}",
-                            target.name(),
-                            package
+                }
+            }
+        }
+    }
+
+    /// Resolve packages to their canonical identifiers, following renames and transfers.
+    ///
+    /// Packages collapsing onto the same repository are deduplicated.
+    /// Packages whose repository is archived or gone are returned separately with their outcome.
+    pub fn canonicalize(
+        &mut self,
+        packages: Vec<Package>,
+    ) -> (Vec<Package>, Vec<(Package, Outcome)>) {
+        let mut seen = HashSet::new();
+        let mut canonical = vec![];
+        let mut rejected = vec![];
+        for package in packages {
+            self.logger.set_message(&package);
+            let result = match self.init_target(package.target) {
+                Some(true) => {
+                    let (target, _) = &self.targets[package.target];
+                    target.canonicalize(self.logger, &package)
+                }
+                // Leave it to `star` to report.
+                _ => Ok(Canonical::Active(package.identifier.clone())),
+            };
+            self.logger.with_progress_bar(|pb| pb.inc(1));
+
+            match result {
+                Ok(Canonical::Active(identifier)) => {
+                    if identifier != package.identifier {
+                        self.logger.debug(format!(
+                            "{}: {} is now {}",
+                            package, package.identifier, identifier
                         ));
-                        break;
+                    }
+                    if seen.insert((package.target, identifier.clone())) {
+                        canonical.push(Package {
+                            identifier,
+                            ..package
+                        });
+                    } else {
+                        self.logger.debug(format!(
+                            "{}: {} is already listed, skipped",
+                            package, identifier
+                        ));
+                    }
+                }
+                Ok(Canonical::Archived(identifier)) => {
+                    self.logger
+                        .warn(format!("{}: {} is archived", package, identifier));
+                    rejected.push((
+                        Package {
+                            identifier,
+                            ..package
+                        },
+                        Outcome::Archived,
+                    ));
+                }
+                Ok(Canonical::NotFound) => {
+                    self.logger
+                        .warn(format!("{}: {} not found", package, package.identifier));
+                    rejected.push((package, Outcome::NotFound));
+                }
+                Err(e) => {
+                    // Still try to star it with the original identifier.
+                    self.logger.warn(format!(
+                        "failed to canonicalize {}: {}",
+                        package.identifier, e
+                    ));
+                    if seen.insert((package.target, package.identifier.clone())) {
+                        canonical.push(package);
                     }
                 }
             }
-        } else {
-            self.logger
-                .error(format!("no such target found: {}", package.target));
         }
+        (canonical, rejected)
+    }
+
+    /// Targets given up for authentication failures, either aborted or failing to initialize
+    /// because their credentials are rejected.
+    ///
+    /// Targets left without credentials, e.g. as the user declined to enter one, are not counted.
+    pub fn auth_failures(&self) -> Vec<&'static str> {
+        self.targets
+            .iter()
+            .filter(|(name, (_, state))| match state {
+                TargetState::Aborted => true,
+                TargetState::Failed => self.credentials.rejected(name),
+                _ => false,
+            })
+            .map(|(name, _)| *name)
+            .sorted()
+            .collect()
+    }
+
+    /// Abort the target if its credential is rejected too many times in a row.
+    fn count_unauthorized(&mut self, name: &'static str) {
+        let count = self.unauthorized.entry(name).or_default();
+        *count += 1;
+        if *count >= MAX_UNAUTHORIZED {
+            self.logger.error(format!(
+                "credential of target {} rejected {} times in a row, aborted",
+                name, count
+            ));
+            if let Some((_, state)) = self.targets.get_mut(name) {
+                *state = TargetState::Aborted;
+            }
+        }
+    }
+
+    /// Initialize the target if not yet.
+    ///
+    /// Return `None` if there's no such target, or whether the target is initialized.
+    fn init_target(&mut self, name: &'static str) -> Option<bool> {
+        let (target, state) = self.targets.get_mut(name)?;
+        if let TargetState::Uninitialized = state {
+            *state = if target.init(self.logger, self.persist, &self.credentials) {
+                let handled = load_handled(self.persist, &**target);
+                self.handled.insert(name, handled);
+                TargetState::Initialized
+            } else {
+                TargetState::Failed
+            };
+        }
+        Some(matches!(state, TargetState::Initialized))
+    }
+
+    /// Persist identifiers handled so far, so that incremental runs can skip them.
+    pub fn save_handled(&mut self) {
+        let entries: Vec<_> = self
+            .handled
+            .iter()
+            .filter_map(|(name, handled)| {
+                let (target, _) = self.targets.get(name)?;
+                Some((
+                    name.to_string(),
+                    json!({
+                        "fingerprint": target.fingerprint(),
+                        "handled": handled.iter().sorted().collect::<Vec<_>>(),
+                    }),
+                ))
+            })
+            .collect();
+        if entries.is_empty() {
+            return;
+        }
+        self.persist.with_state(|state| {
+            let incremental = state
+                .entry("incremental")
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+                .expect("incremental state is an object");
+            incremental.extend(entries);
+        });
     }
 }
 
+/// A target of another account, named `target@profile`.
+struct Profiled<T> {
+    inner: T,
+    name: &'static str,
+    profile: &'static str,
+}
+
+impl<T: Target> Target for Profiled<T> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn init(&mut self, logger: &Logger, persist: &mut Persist, credentials: &Credentials) -> bool {
+        let inner = &mut self.inner;
+        credentials.with_profile(self.profile, self.name, || {
+            inner.init(logger, persist, credentials)
+        })
+    }
+
+    fn try_handle(&self, url: &Url) -> Option<String> {
+        self.inner.try_handle(url)
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        self.inner.fingerprint()
+    }
+
+    fn canonicalize(&self, logger: &Logger, package: &Package) -> Result<Canonical, BoxedError> {
+        self.inner.canonicalize(logger, package)
+    }
+
+    fn star(&self, logger: &Logger, package: &Package) -> Result<StarStatus, Error> {
+        self.inner.star(logger, package)
+    }
+}
+
+/// Load identifiers handled in previous runs on the given target.
+///
+/// Handled identifiers are discarded if the credential of the target has changed since.
+fn load_handled(persist: &Persist, target: &dyn Target) -> HashSet<String> {
+    persist.get_state(|state| {
+        state
+            .get("incremental")
+            .and_then(|incremental| incremental.get(target.name()))
+            .filter(|entry| entry.get("fingerprint") == Some(&json!(target.fingerprint())))
+            .and_then(|entry| entry.get("handled"))
+            .and_then(Value::as_array)
+            .map(|handled| {
+                handled
+                    .iter()
+                    .filter_map(|id| id.as_str().map(ToString::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
 /// Registry for sources.
 pub struct SourceRegistry<'a> {
     sources: Vec<Box<dyn Source>>,
+    resolvers: Vec<Box<dyn Resolver>>,
     logger: &'a Logger,
 }
 
@@ -102,6 +582,7 @@ impl<'a> SourceRegistry<'a> {
     pub fn new(logger: &'a Logger) -> Self {
         Self {
             sources: vec![],
+            resolvers: vec![],
             logger,
         }
     }
@@ -111,25 +592,21 @@ impl<'a> SourceRegistry<'a> {
             self.sources.push(Box::new(source));
         }
     }
-    /// Deregister a source.
-    pub fn deregister(&mut self, name: &str) -> bool {
-        if let Some((idx, _)) = self
-            .sources
-            .iter()
-            .enumerate()
-            .find(|(_, s)| s.name() == name)
-        {
-            self.sources.remove(idx);
-            true
-        } else {
-            false
-        }
+    /// Register a resolver.
+    ///
+    /// Resolvers are consulted in registration order for packages not recognized by any target.
+    pub fn register_resolver(&mut self, resolver: impl Resolver) {
+        self.resolvers.push(Box::new(resolver));
+    }
+    /// Deregister sources for which `f` returns `false`.
+    pub fn retain(&mut self, f: impl Fn(&str) -> bool) {
+        self.sources.retain(|source| f(source.name()));
     }
     /// Aggregate packages from all sources.
     ///
     /// If a local source is matched, all global sources will be ignored.
     /// Files will be read and passed to the local source.
-    pub fn aggregate(&self, targets: &TargetRegistry) -> Vec<Package> {
+    pub fn aggregate(&self, targets: &TargetRegistry) -> Aggregation {
         let cache: HashMap<&str, Vec<u8>> = self
             .sources
             .iter()
@@ -146,48 +623,569 @@ impl<'a> SourceRegistry<'a> {
             .collect();
         let global_mode = cache.is_empty();
 
-        self.sources
-            .iter()
-            .flat_map(|source| match source.source_type() {
+        let mut aggregation = Aggregation::default();
+        for source in &self.sources {
+            let candidates = match source.source_type() {
                 SourceType::Global if global_mode => {
                     self.logger.set_progress_bar_spinner();
                     source
-                        .snapshot(self.logger, HashMap::new(), targets)
-                        .tap_err(|e| {
-                            self.logger.warn(format!(
-                                "failed to snapshot {}: {}",
-                                source.name(),
-                                e
-                            ));
-                        })
+                        .snapshot(self.logger, HashMap::new())
+                        .tap_err(|e| self.snapshot_failed(&**source, e))
                         .unwrap_or_default()
                 }
                 SourceType::Local(filenames) if !global_mode => filenames
                     .iter()
-                    .fold(Some(HashMap::new()), |acc, x| {
-                        acc.and_then(|mut acc| {
-                            cache.get(x).map(|file| {
-                                acc.insert(*x, &file[..]);
-                                acc
-                            })
+                    .try_fold(HashMap::new(), |mut acc, x| {
+                        cache.get(x).map(|file| {
+                            acc.insert(*x, &file[..]);
+                            acc
                         })
                     })
                     .map_or(vec![], |files| {
                         self.logger.set_progress_bar_spinner();
                         source
-                            .snapshot(self.logger, files, targets)
-                            .tap_err(|e| {
-                                self.logger.warn(format!(
-                                    "failed to snapshot {}: {}",
-                                    source.name(),
-                                    e
-                                ));
-                            })
+                            .snapshot(self.logger, files)
+                            .tap_err(|e| self.snapshot_failed(&**source, e))
                             .unwrap_or_default()
                     }),
-                _ => vec![],
-            })
-            .unique_by(|package| package.identifier.clone())
-            .collect()
+                _ => continue,
+            };
+
+            let mut stats = SourceStats {
+                source: source.name(),
+                resolved: 0,
+                total: candidates.len(),
+            };
+            for candidate in candidates {
+                let mut candidate = Candidate {
+                    source: source.name(),
+                    ..candidate
+                };
+                if let Some(package) = targets
+                    .resolve(&candidate)
+                    .or_else(|| self.resolve(&**source, targets, &mut candidate))
+                {
+                    stats.resolved += 1;
+                    aggregation.packages.push(package);
+                } else {
+                    aggregation.unresolved.push(candidate);
+                }
+            }
+            aggregation.stats.push(stats);
+        }
+
+        aggregation.packages = aggregation
+            .packages
+            .into_iter()
+            // The same identifier may refer to different repositories on different targets.
+            .unique_by(|package| (package.target, package.identifier.clone()))
+            .collect();
+        aggregation
+    }
+
+    fn snapshot_failed(&self, source: &dyn Source, e: &Error) {
+        if let Error::CommandNotFound(command) = e {
+            // The source is available, but the command it relies on is gone.
+            self.logger.debug(format!(
+                "skipped {}: command {} not found",
+                source.name(),
+                command
+            ));
+        } else {
+            self.logger
+                .warn(format!("failed to snapshot {}: {}", source.name(), e));
+        }
+    }
+
+    /// Find more urls of the candidate through resolvers, and try to match them.
+    ///
+    /// Urls found are appended to the candidate.
+    fn resolve(
+        &self,
+        source: &dyn Source,
+        targets: &TargetRegistry,
+        candidate: &mut Candidate,
+    ) -> Option<Package> {
+        self.resolvers.iter().find_map(|resolver| {
+            let urls = resolver
+                .resolve(self.logger, source, candidate)
+                .tap_err(|e| {
+                    self.logger.debug(format!(
+                        "{} failed to resolve {}: {}",
+                        resolver.name(),
+                        candidate.name,
+                        e
+                    ));
+                })
+                .unwrap_or_default();
+            let package = urls
+                .iter()
+                .find_map(|url| targets.try_parse(candidate.name.clone(), url))
+                .map(|package| Package {
+                    source: candidate.source,
+                    ..package
+                });
+            candidate.urls.extend(urls);
+            package
+        })
+    }
+}
+
+/// Packages aggregated from all sources.
+#[derive(Debug, Default)]
+pub struct Aggregation {
+    /// Packages recognized by a target, deduplicated by identifier.
+    pub packages: Vec<Package>,
+    /// Packages not recognized by any target.
+    pub unresolved: Vec<Candidate>,
+    /// Statistics of each snapshotted source.
+    pub stats: Vec<SourceStats>,
+}
+
+/// Statistics of a source.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SourceStats {
+    /// Name of the source.
+    pub source: &'static str,
+    /// Number of packages recognized by a target, before deduplication.
+    pub resolved: usize,
+    /// Number of all packages found.
+    pub total: usize,
+}
+
+impl Display for SourceStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        #[allow(clippy::cast_precision_loss)]
+        let ratio = if self.total == 0 {
+            0.
+        } else {
+            self.resolved as f64 / self.total as f64 * 100.
+        };
+        write!(
+            f,
+            "{}: {}/{} packages ({:.1}%) have a recognized upstream",
+            self.source, self.resolved, self.total, ratio
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::Duration;
+
+    use url::Url;
+
+    use crate::common::{
+        BoxedError, Candidate, Canonical, Error, InstanceSpec, Outcome, Package, Source,
+        SourceType, StarStatus, Target,
+    };
+    use crate::credential::{CredentialSpec, Credentials, InvalidCredential};
+    use crate::github::Github;
+    use crate::gitlab::Gitlab;
+    use crate::secret::SecretStore;
+    use crate::tests::{DebugTarget, Fixture};
+    use crate::{Logger, Persist};
+
+    use super::{SourceRegistry, TargetRegistry};
+
+    #[test]
+    fn test_incremental() {
+        let fixture = Fixture::default();
+        let url = Url::parse("https://github.com/PhotonQuantum/stars").unwrap();
+
+        let package = fixture.with_registry(|targets| {
+            targets.register(DebugTarget::default());
+            let package = targets.try_parse(String::from("stars"), &url).unwrap();
+            assert_eq!(targets.star(&package), Outcome::Starred);
+            targets.save_handled();
+            package
+        });
+
+        fixture.with_registry(|targets| {
+            targets.register(DebugTarget::default());
+            let (pending, handled) = targets.split_handled(vec![package.clone()]);
+            assert_eq!((pending.len(), handled.len()), (1, 0));
+
+            targets.set_incremental(true);
+            let (pending, handled) = targets.split_handled(vec![package.clone()]);
+            assert!(pending.is_empty());
+            assert_eq!(handled[0].identifier, package.identifier);
+        });
+    }
+
+    /// Target of repos renamed from `old/*` to `new/*`.
+    struct RenamingTarget;
+
+    impl Target for RenamingTarget {
+        fn name(&self) -> &'static str {
+            "renaming"
+        }
+
+        fn init(
+            &mut self,
+            _logger: &Logger,
+            _persist: &mut Persist,
+            _credentials: &Credentials,
+        ) -> bool {
+            true
+        }
+
+        fn try_handle(&self, url: &Url) -> Option<String> {
+            Some(url.path().trim_start_matches('/').to_string())
+        }
+
+        fn canonicalize(
+            &self,
+            _logger: &Logger,
+            package: &Package,
+        ) -> Result<Canonical, BoxedError> {
+            Ok(Canonical::Active(
+                package.identifier.replacen("old/", "new/", 1),
+            ))
+        }
+
+        fn star(&self, _logger: &Logger, _package: &Package) -> Result<StarStatus, Error> {
+            Ok(StarStatus::Starred)
+        }
+    }
+
+    #[test]
+    fn test_register_instance() {
+        let fixture = Fixture::default();
+        fixture.with_registry(|targets| {
+            let known = Gitlab::known_instances();
+            targets.register_profiled(Gitlab::default).unwrap();
+            for gitlab in &known {
+                targets.register_profiled(|| Gitlab::new(gitlab)).unwrap();
+            }
+            let parse = |targets: &TargetRegistry, url: &str| {
+                targets
+                    .try_parse(String::from("repo"), &Url::parse(url).unwrap())
+                    .map(|package| package.target)
+            };
+
+            // Built-in targets can't be replaced.
+            let spec: InstanceSpec = "gitlab=https://git.example.com".parse().unwrap();
+            assert!(targets
+                .register_instance(&spec, &known, || Gitlab::new(&spec))
+                .is_err());
+
+            // Known instances of the same forge can.
+            let spec: InstanceSpec = "gitlab-gnome=https://gnome.example.com".parse().unwrap();
+            targets
+                .register_instance(&spec, &known, || Gitlab::new(&spec))
+                .unwrap();
+            assert_eq!(
+                parse(targets, "https://gnome.example.com/team/repo"),
+                Some("gitlab-gnome")
+            );
+            assert_eq!(parse(targets, "https://gitlab.gnome.org/team/repo"), None);
+
+            // Instances sharing a host are matched in registration order.
+            for name in ["first", "second"] {
+                let spec: InstanceSpec =
+                    format!("{}=https://git.example.com", name).parse().unwrap();
+                targets
+                    .register_instance(&spec, &known, || Gitlab::new(&spec))
+                    .unwrap();
+            }
+            assert_eq!(
+                parse(targets, "https://git.example.com/team/repo"),
+                Some("first")
+            );
+        });
+    }
+
+    #[test]
+    fn test_register_instance_profiles() {
+        let fixture = Fixture::default();
+        fixture.with_registry(|targets| {
+            targets.set_profiles(vec![String::from("bot")]);
+            let known = Gitlab::known_instances();
+            targets.register_profiled(Github::default).unwrap();
+            for gitlab in &known {
+                targets.register_profiled(|| Gitlab::new(gitlab)).unwrap();
+            }
+            let parse = |targets: &TargetRegistry, url: &str| {
+                targets
+                    .try_parse(String::from("repo"), &Url::parse(url).unwrap())
+                    .map(|package| package.target)
+            };
+
+            // Targets are taken by their base name, even if only other profiles are selected.
+            let spec: InstanceSpec = "github=https://ghe.example.com".parse().unwrap();
+            assert!(targets
+                .register_instance(&spec, &[], || Github::enterprise(&spec))
+                .is_err());
+            assert!(targets.register_profiled(Github::default).is_err());
+
+            let spec: InstanceSpec = "gitlab-gnome=https://gnome.example.com".parse().unwrap();
+            targets
+                .register_instance(&spec, &known, || Gitlab::new(&spec))
+                .unwrap();
+            assert_eq!(
+                parse(targets, "https://gnome.example.com/team/repo"),
+                Some("gitlab-gnome@bot")
+            );
+            assert_eq!(parse(targets, "https://gitlab.gnome.org/team/repo"), None);
+
+            assert!(targets.deregister("gitlab-gnome"));
+            assert_eq!(parse(targets, "https://gnome.example.com/team/repo"), None);
+        });
+    }
+
+    #[test]
+    fn test_canonicalize_dedup() {
+        let fixture = Fixture::default();
+        let old = Url::parse("https://example.org/old/stars").unwrap();
+        let new = Url::parse("https://example.org/new/stars").unwrap();
+
+        fixture.with_registry(|targets| {
+            targets.register(RenamingTarget);
+            let packages = vec![
+                targets.try_parse(String::from("stars"), &old).unwrap(),
+                targets.try_parse(String::from("stars-bin"), &new).unwrap(),
+            ];
+            assert_ne!(packages[0].identifier, packages[1].identifier);
+
+            // The old name resolves to the new one, so both packages collapse into one.
+            let (canonical, rejected) = targets.canonicalize(packages);
+            assert_eq!(canonical.len(), 1);
+            assert_eq!(canonical[0].name, "stars");
+            assert_eq!(canonical[0].identifier, "new/stars");
+            assert!(rejected.is_empty());
+        });
+    }
+
+    struct RejectingTarget;
+
+    impl Target for RejectingTarget {
+        fn name(&self) -> &'static str {
+            "rejecting"
+        }
+
+        fn init(
+            &mut self,
+            _logger: &Logger,
+            _persist: &mut Persist,
+            _credentials: &Credentials,
+        ) -> bool {
+            true
+        }
+
+        fn try_handle(&self, url: &Url) -> Option<String> {
+            Some(url.to_string())
+        }
+
+        fn star(&self, _logger: &Logger, _package: &Package) -> Result<StarStatus, Error> {
+            Err(Error::Unauthorized(String::new()))
+        }
+    }
+
+    #[test]
+    fn test_abort_unauthorized() {
+        let fixture = Fixture::default();
+        let url = Url::parse("https://github.com/PhotonQuantum/stars").unwrap();
+
+        fixture.with_registry(|targets| {
+            targets.register(RejectingTarget);
+            let package = targets.try_parse(String::from("stars"), &url).unwrap();
+
+            let outcomes: Vec<_> = (0..5).map(|_| targets.star(&package).kind()).collect();
+            assert_eq!(
+                outcomes,
+                ["failed", "failed", "failed", "skipped", "skipped"]
+            );
+            assert_eq!(targets.auth_failures(), ["rejecting"]);
+        });
+    }
+
+    /// Target logging in with a credential that is always rejected.
+    struct LoginTarget(&'static str);
+
+    impl Target for LoginTarget {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn init(
+            &mut self,
+            logger: &Logger,
+            _persist: &mut Persist,
+            credentials: &Credentials,
+        ) -> bool {
+            let prompt = |_: &Logger| None;
+            let verify = |_: &str| -> Result<(), BoxedError> {
+                Err(InvalidCredential(String::from("rejected")).into())
+            };
+            let spec =
+                CredentialSpec::new(self.0, format!("{}_token", self.0), &prompt).verify(&verify);
+            credentials.lookup(logger, &spec).is_some()
+        }
+
+        fn try_handle(&self, url: &Url) -> Option<String> {
+            Some(url.to_string())
+        }
+
+        fn star(&self, _logger: &Logger, _package: &Package) -> Result<StarStatus, Error> {
+            Ok(StarStatus::Starred)
+        }
+    }
+
+    #[test]
+    fn test_auth_failures() {
+        let fixture = Fixture::default();
+        let token = fixture.path("token");
+        fs::write(&token, "expired").unwrap();
+        let url = Url::parse("https://github.com/PhotonQuantum/stars").unwrap();
+
+        fixture.with_registry(|targets| {
+            targets.register(LoginTarget("rejected"));
+            targets.register(LoginTarget("missing"));
+            targets.set_credentials(Credentials::new(
+                [format!("rejected={}", token.display()).parse().unwrap()],
+                SecretStore::default(),
+            ));
+            for target in ["rejected", "missing"] {
+                let package =
+                    Package::new(String::from("stars"), url.clone(), url.to_string(), target);
+                assert_eq!(targets.star(&package).kind(), "skipped");
+            }
+
+            // Only rejected credentials count, not missing ones.
+            assert_eq!(targets.auth_failures(), ["rejected"]);
+        });
+    }
+
+    /// Target replaying the given results of star actions.
+    struct ScriptedTarget(RefCell<Vec<Result<StarStatus, Error>>>);
+
+    impl Target for ScriptedTarget {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn init(
+            &mut self,
+            _logger: &Logger,
+            _persist: &mut Persist,
+            _credentials: &Credentials,
+        ) -> bool {
+            true
+        }
+
+        fn try_handle(&self, url: &Url) -> Option<String> {
+            Some(url.to_string())
+        }
+
+        fn star(&self, _logger: &Logger, _package: &Package) -> Result<StarStatus, Error> {
+            self.0.borrow_mut().remove(0)
+        }
+    }
+
+    #[test]
+    fn test_error_classes() {
+        let fixture = Fixture::default();
+        let url = Url::parse("https://github.com/PhotonQuantum/stars").unwrap();
+
+        fixture.with_registry(|targets| {
+            targets.register(ScriptedTarget(RefCell::new(vec![
+                Err(Error::RateLimited {
+                    retry_after: Some(Duration::ZERO),
+                }),
+                Ok(StarStatus::Starred),
+                Err(Error::NotFound),
+                Err(Error::Network(String::from("connection reset"))),
+            ])));
+            let package = targets.try_parse(String::from("stars"), &url).unwrap();
+
+            // Rate limited actions are retried.
+            assert_eq!(targets.star(&package), Outcome::Starred);
+            assert_eq!(targets.star(&package), Outcome::NotFound);
+            assert_eq!(targets.star(&package).kind(), "failed");
+        });
+    }
+
+    #[test]
+    fn test_accounts() {
+        let fixture = Fixture::default();
+        let url = Url::parse("https://github.com/PhotonQuantum/stars").unwrap();
+
+        fixture.with_registry(|targets| {
+            targets.set_profiles(vec![String::from("default"), String::from("bot")]);
+            targets.register_profiled(DebugTarget::default).unwrap();
+
+            let package = targets.try_parse(String::from("stars"), &url).unwrap();
+            assert_eq!(package.target, "debug");
+            let packages = targets.expand_accounts(vec![package]);
+            assert_eq!(
+                packages.iter().map(|p| p.target).collect::<Vec<_>>(),
+                ["debug", "debug@bot"]
+            );
+            for package in &packages {
+                assert_eq!(targets.star(package), Outcome::Starred);
+            }
+
+            targets.deregister("debug");
+            assert!(targets.try_parse(String::from("stars"), &url).is_none());
+            assert_eq!(targets.star(&packages[1]).kind(), "failed");
+        });
+    }
+
+    struct FixedSource(Vec<Candidate>);
+
+    impl Source for FixedSource {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn source_type(&self) -> SourceType {
+            SourceType::Global
+        }
+
+        fn available(&self) -> bool {
+            true
+        }
+
+        fn snapshot(
+            &self,
+            _logger: &Logger,
+            _files: HashMap<&str, &[u8]>,
+        ) -> Result<Vec<Candidate>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_aggregate_dedup() {
+        let fixture = Fixture::default();
+        fixture.with_registry(|targets| {
+            targets.register_profiled(Gitlab::default).unwrap();
+            for gitlab in &Gitlab::known_instances() {
+                targets.register_profiled(|| Gitlab::new(gitlab)).unwrap();
+            }
+            let candidate = |name: &str, url: &str| {
+                Candidate::new(String::from(name), vec![Url::parse(url).unwrap()])
+            };
+
+            let mut sources = SourceRegistry::new(&fixture.logger);
+            sources.register(FixedSource(vec![
+                candidate("repo", "https://gitlab.com/team/repo"),
+                candidate("repo-git", "https://gitlab.com/team/repo"),
+                candidate("repo-gnome", "https://gitlab.gnome.org/team/repo"),
+            ]));
+            let aggregation = sources.aggregate(targets);
+            assert_eq!(
+                aggregation
+                    .packages
+                    .iter()
+                    .map(|package| (package.name.as_str(), package.target))
+                    .collect::<Vec<_>>(),
+                [("repo", "gitlab"), ("repo-gnome", "gitlab-gnome")]
+            );
+        });
     }
 }
@@ -0,0 +1,174 @@
+//! Repology integration.
+//!
+//! Repology aggregates package metadata of many distributions. For a package without any
+//! recognizable url, upstream links reported by other distributions often point to its
+//! repository.
+
+use std::cell::Cell;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use serde::Deserialize;
+use url::Url;
+
+use crate::cache::Cache;
+use crate::common::{BoxedError, Candidate, Resolver, Source};
+use crate::normalize::parse_url;
+use crate::Logger;
+
+/// Repology asks API users not to exceed one request per second.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Kind of package names used by a repository.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NameType {
+    /// Source package name (e.g., `dpkg` source packages, `portage` atoms).
+    SrcName,
+    /// Binary package name (e.g., `pacman`, `rpm` packages).
+    BinName,
+}
+
+impl NameType {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::SrcName => "srcname",
+            Self::BinName => "binname",
+        }
+    }
+}
+
+/// A repository tracked by Repology.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RepologyRepo {
+    /// Repository identifier on Repology (e.g., `arch`, `debian_unstable`).
+    pub repo: &'static str,
+    /// Kind of package names reported by the source.
+    pub name_type: NameType,
+}
+
+impl RepologyRepo {
+    pub const fn new(repo: &'static str, name_type: NameType) -> Self {
+        Self { repo, name_type }
+    }
+}
+
+/// Resolver looking up upstream links of packages on Repology.
+pub struct Repology {
+    cache: Cache,
+    last_request: Cell<Option<Instant>>,
+}
+
+impl Repology {
+    pub const fn new(cache: Cache) -> Self {
+        Self {
+            cache,
+            last_request: Cell::new(None),
+        }
+    }
+
+    fn throttle(&self) {
+        if let Some(last) = self.last_request.get() {
+            if let Some(remaining) = REQUEST_INTERVAL.checked_sub(last.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+        self.last_request.set(Some(Instant::now()));
+    }
+}
+
+impl Resolver for Repology {
+    fn name(&self) -> &'static str {
+        "repology"
+    }
+
+    fn resolve(
+        &self,
+        logger: &Logger,
+        source: &dyn Source,
+        candidate: &Candidate,
+    ) -> Result<Vec<Url>, BoxedError> {
+        let Some(repo) = source.repology_repo() else {
+            return Ok(vec![]);
+        };
+
+        let name = candidate
+            .qualified_name
+            .as_deref()
+            .unwrap_or(&candidate.name);
+        // `project-by` maps a package name in the given repository to its Repology project,
+        // and redirects to the API listing packages of the project in all repositories.
+        let url = format!(
+            "https://repology.org/tools/project-by?repo={}&name_type={}&target_page=api_v1_project&name={}",
+            repo.repo,
+            repo.name_type.as_str(),
+            urlencoding::encode(name)
+        );
+        let key = format!("{}/{}", repo.repo, name);
+
+        logger.set_message(format!("{} (repology)", candidate.name));
+        if !self.cache.is_fresh("repology", &key) {
+            self.throttle();
+        }
+        let packages: Vec<RepologyPackage> = self.cache.get_json("repology", &key, &url)?;
+
+        Ok(upstream_links(&packages)
+            .into_iter()
+            .filter(|url| !candidate.urls.contains(url))
+            .collect())
+    }
+}
+
+/// A package of a Repology project in some repository.
+///
+/// Only upstream links are read. Other links (e.g., package recipes and patches) point to
+/// distribution repositories, which must not be starred.
+#[derive(Debug, Deserialize)]
+struct RepologyPackage {
+    /// Upstream source repository.
+    srcurl: Option<String>,
+    /// Upstream homepage.
+    homepage: Option<String>,
+}
+
+/// Upstream links of a project, source repositories first.
+fn upstream_links(packages: &[RepologyPackage]) -> Vec<Url> {
+    let srcurls = packages
+        .iter()
+        .filter_map(|package| package.srcurl.as_deref());
+    let homepages = packages
+        .iter()
+        .filter_map(|package| package.homepage.as_deref());
+    srcurls
+        .chain(homepages)
+        .filter_map(parse_url)
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{upstream_links, RepologyPackage};
+
+    #[test]
+    fn test_upstream_links() {
+        let packages: Vec<RepologyPackage> = serde_json::from_str(
+            r#"[
+                {"repo": "arch", "binname": "fd", "homepage": "https://github.com/sharkdp/fd"},
+                {"repo": "gentoo", "srcname": "sys-apps/fd", "srcurl": "git+https://github.com/sharkdp/fd.git"},
+                {"repo": "debian_unstable", "srcname": "rust-fd-find", "homepage": "https://github.com/sharkdp/fd"},
+                {"repo": "nix_unstable", "srcname": "fd", "version": "10.1.0"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            upstream_links(&packages),
+            vec![
+                Url::parse("https://github.com/sharkdp/fd.git").unwrap(),
+                Url::parse("https://github.com/sharkdp/fd").unwrap(),
+            ]
+        );
+    }
+}
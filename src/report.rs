@@ -0,0 +1,457 @@
+//! Machine-readable run reports.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Write as _};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+
+use crate::common::{BoxedError, Candidate, Outcome, Package};
+use crate::registry::SourceStats;
+
+/// Format of a report.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Json,
+    Csv,
+    #[serde(alias = "md")]
+    Markdown,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "markdown" | "md" => Ok(Self::Markdown),
+            _ => Err(format!(
+                "unknown report format: {} (expected json, csv or markdown)",
+                s
+            )),
+        }
+    }
+}
+
+impl ReportFormat {
+    /// Guess the format from the extension of the path. Defaults to json.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+            .unwrap_or(Self::Json)
+    }
+}
+
+/// An entry of the report.
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    source: &'a str,
+    target: &'a str,
+    identifier: &'a str,
+    url: &'a str,
+    #[serde(flatten)]
+    outcome: &'a Outcome,
+}
+
+const COLUMNS: [&str; 7] = [
+    "name",
+    "source",
+    "target",
+    "identifier",
+    "url",
+    "outcome",
+    "reason",
+];
+
+/// An unresolved package in the report.
+#[derive(Debug, Serialize)]
+struct UnresolvedEntry<'a> {
+    name: &'a str,
+    source: &'a str,
+    urls: Vec<&'a str>,
+}
+
+/// Statistics of a source in the report.
+#[derive(Debug, Serialize)]
+struct StatsEntry {
+    source: &'static str,
+    resolved: usize,
+    total: usize,
+}
+
+/// Report of all discovered packages and their outcomes in a run.
+#[derive(Debug, Default)]
+pub struct Report {
+    entries: Vec<(Package, Outcome)>,
+    unresolved: Vec<Candidate>,
+    stats: Vec<SourceStats>,
+}
+
+impl Report {
+    /// Add a package and its outcome to the report.
+    pub fn push(&mut self, package: &Package, outcome: Outcome) {
+        self.entries.push((package.clone(), outcome));
+    }
+    /// Add packages not recognized by any target to the report.
+    pub fn push_unresolved(&mut self, unresolved: &[Candidate]) {
+        self.unresolved.extend_from_slice(unresolved);
+    }
+    /// Add statistics of sources to the report.
+    pub fn push_stats(&mut self, stats: &[SourceStats]) {
+        self.stats.extend_from_slice(stats);
+    }
+    /// Render the report in the given format.
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Json => {
+                let entries: Vec<_> = self
+                    .entries
+                    .iter()
+                    .map(|(package, outcome)| Entry {
+                        name: &package.name,
+                        source: package.source,
+                        target: package.target,
+                        identifier: &package.identifier,
+                        url: package.url.as_str(),
+                        outcome,
+                    })
+                    .collect();
+                let unresolved: Vec<_> = self
+                    .unresolved
+                    .iter()
+                    .map(|candidate| UnresolvedEntry {
+                        name: &candidate.name,
+                        source: candidate.source,
+                        urls: candidate.urls.iter().map(Url::as_str).collect(),
+                    })
+                    .collect();
+                let sources: Vec<_> = self
+                    .stats
+                    .iter()
+                    .map(|stats| StatsEntry {
+                        source: stats.source,
+                        resolved: stats.resolved,
+                        total: stats.total,
+                    })
+                    .collect();
+                serde_json::to_string_pretty(&json!({
+                    "packages": entries,
+                    "unresolved": unresolved,
+                    "sources": sources,
+                }))
+                .unwrap()
+            }
+            ReportFormat::Csv => {
+                let mut output = COLUMNS.join(",");
+                output.push('\n');
+                for row in self.rows() {
+                    let row: Vec<_> = row.iter().map(|field| csv_escape(field)).collect();
+                    output.push_str(&row.join(","));
+                    output.push('\n');
+                }
+                output
+            }
+            ReportFormat::Markdown => {
+                let mut output = format!(
+                    "| {} |\n|{}\n",
+                    COLUMNS.join(" | "),
+                    "---|".repeat(COLUMNS.len())
+                );
+                for row in self.rows() {
+                    let row: Vec<_> = row.iter().map(|field| markdown_escape(field)).collect();
+                    writeln!(output, "| {} |", row.join(" | ")).unwrap();
+                }
+                if !self.stats.is_empty() {
+                    output.push_str("\n| source | resolved | total |\n|---|---|---|\n");
+                    for stats in &self.stats {
+                        writeln!(
+                            output,
+                            "| {} | {} | {} |",
+                            stats.source, stats.resolved, stats.total
+                        )
+                        .unwrap();
+                    }
+                }
+                output
+            }
+        }
+    }
+    /// Count outcomes of each target.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary {
+            unresolved: self.unresolved.len(),
+            ..Summary::default()
+        };
+        for (package, outcome) in &self.entries {
+            let counts = summary.targets.entry(package.target).or_default();
+            match outcome {
+                Outcome::Starred => counts.starred += 1,
+                Outcome::AlreadyStarred => counts.already_starred += 1,
+                Outcome::Failed(_) => counts.failed += 1,
+                Outcome::Archived
+                | Outcome::NotFound
+                | Outcome::Skipped(_)
+                | Outcome::Filtered(_) => counts.skipped += 1,
+            }
+        }
+        summary
+    }
+    /// Write the report to the given path.
+    pub fn write(&self, path: impl AsRef<Path>, format: ReportFormat) -> Result<(), BoxedError> {
+        fs::write(path, self.render(format))?;
+        Ok(())
+    }
+
+    fn rows(&self) -> impl Iterator<Item = [String; 7]> + '_ {
+        let unresolved = self.unresolved.iter().map(|candidate| {
+            [
+                candidate.name.clone(),
+                candidate.source.to_string(),
+                String::new(),
+                String::new(),
+                candidate.urls.iter().join(" "),
+                String::from("unresolved"),
+                String::new(),
+            ]
+        });
+        self.entries
+            .iter()
+            .map(|(package, outcome)| {
+                let reason = match outcome {
+                    Outcome::Failed(reason)
+                    | Outcome::Skipped(reason)
+                    | Outcome::Filtered(reason) => reason.clone(),
+                    _ => String::new(),
+                };
+                [
+                    package.name.clone(),
+                    package.source.to_string(),
+                    package.target.to_string(),
+                    package.identifier.clone(),
+                    package.url.to_string(),
+                    outcome.kind().to_string(),
+                    reason,
+                ]
+            })
+            .chain(unresolved)
+    }
+}
+
+/// Outcome counts of a target.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Counts {
+    pub starred: usize,
+    pub already_starred: usize,
+    pub failed: usize,
+    /// Including archived, vanished and filtered repositories.
+    pub skipped: usize,
+}
+
+impl Display for Counts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} starred, {} already starred, {} failed, {} skipped",
+            self.starred, self.already_starred, self.failed, self.skipped
+        )
+    }
+}
+
+/// Summary of a run.
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Outcome counts of each target.
+    pub targets: BTreeMap<&'static str, Counts>,
+    /// Number of packages not recognized by any target.
+    pub unresolved: usize,
+}
+
+impl Summary {
+    /// Overall status of the run.
+    ///
+    /// `auth_failed` tells whether any target was given up for authentication failures, which
+    /// takes precedence over other failures.
+    pub fn status(&self, auth_failed: bool) -> RunStatus {
+        let (succeeded, failed) =
+            self.targets
+                .values()
+                .fold((0, 0), |(succeeded, failed), counts| {
+                    (
+                        succeeded + counts.starred + counts.already_starred,
+                        failed + counts.failed,
+                    )
+                });
+        if auth_failed {
+            RunStatus::AuthFailure
+        } else if failed == 0 {
+            RunStatus::Success
+        } else if succeeded == 0 {
+            RunStatus::TotalFailure
+        } else {
+            RunStatus::PartialFailure
+        }
+    }
+}
+
+/// Overall status of a run, reflected in the exit code.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RunStatus {
+    /// No star action failed.
+    Success,
+    /// Some star actions failed.
+    PartialFailure,
+    /// Every star action attempted failed.
+    TotalFailure,
+    /// Some targets rejected or lacked credentials.
+    AuthFailure,
+}
+
+impl RunStatus {
+    /// Exit code of the process.
+    ///
+    /// 1 is reserved for invalid arguments and setup errors.
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::Success => 0,
+            Self::PartialFailure => 2,
+            Self::TotalFailure => 3,
+            Self::AuthFailure => 4,
+        }
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escape a table cell, keeping it on one line.
+fn markdown_escape(field: &str) -> String {
+    field
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\r', '\n'], "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::common::{Candidate, Outcome};
+    use crate::registry::SourceStats;
+    use crate::tests::package;
+
+    use super::{Counts, Report, ReportFormat, RunStatus};
+
+    fn report() -> Report {
+        let package = package("stars", "PhotonQuantum/stars", "cargo");
+        let mut report = Report::default();
+        report.push(&package, Outcome::Starred);
+        report.push(
+            &package,
+            Outcome::Failed(String::from("404, \"Not Found\"")),
+        );
+        let mut candidate = Candidate::new(
+            String::from("bash"),
+            vec![Url::parse("https://www.gnu.org/software/bash/").unwrap()],
+        );
+        candidate.source = "pacman";
+        report.push_unresolved(&[candidate]);
+        report.push_stats(&[SourceStats {
+            source: "pacman",
+            resolved: 0,
+            total: 1,
+        }]);
+        report
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            report().render(ReportFormat::Csv),
+            "name,source,target,identifier,url,outcome,reason\n\
+             stars,cargo,github,PhotonQuantum/stars,https://github.com/PhotonQuantum/stars,starred,\n\
+             stars,cargo,github,PhotonQuantum/stars,https://github.com/PhotonQuantum/stars,failed,\"404, \"\"Not Found\"\"\"\n\
+             bash,pacman,,,https://www.gnu.org/software/bash/,unresolved,\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let mut report = report();
+        let package = report.entries[0].0.clone();
+        report.push(
+            &package,
+            Outcome::Failed(String::from("500, \"a | b\"\r\nretry\nlater")),
+        );
+        let markdown = report.render(ReportFormat::Markdown);
+        let lines: Vec<_> = markdown.lines().collect();
+        assert_eq!(
+            lines[..6],
+            [
+                "| name | source | target | identifier | url | outcome | reason |",
+                "|---|---|---|---|---|---|---|",
+                "| stars | cargo | github | PhotonQuantum/stars | https://github.com/PhotonQuantum/stars | starred |  |",
+                "| stars | cargo | github | PhotonQuantum/stars | https://github.com/PhotonQuantum/stars | failed | 404, \"Not Found\" |",
+                "| stars | cargo | github | PhotonQuantum/stars | https://github.com/PhotonQuantum/stars | failed | 500, \"a \\| b\"<br>retry<br>later |",
+                "| bash | pacman |  |  | https://www.gnu.org/software/bash/ | unresolved |  |",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(&report().render(ReportFormat::Json)).unwrap();
+        assert_eq!(json["packages"][0]["outcome"], "starred");
+        assert_eq!(json["packages"][1]["reason"], "404, \"Not Found\"");
+        assert_eq!(
+            json["packages"][1]["url"],
+            "https://github.com/PhotonQuantum/stars"
+        );
+        assert_eq!(json["unresolved"][0]["source"], "pacman");
+        assert_eq!(json["sources"][0]["total"], 1);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut report = report();
+        let summary = report.summary();
+        assert_eq!(
+            summary.targets["github"],
+            Counts {
+                starred: 1,
+                failed: 1,
+                ..Counts::default()
+            }
+        );
+        assert_eq!(summary.unresolved, 1);
+        assert_eq!(summary.status(false), RunStatus::PartialFailure);
+        assert_eq!(summary.status(true), RunStatus::AuthFailure);
+
+        report.entries.remove(0);
+        assert_eq!(report.summary().status(false), RunStatus::TotalFailure);
+        report.entries.clear();
+        assert_eq!(report.summary().status(false), RunStatus::Success);
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ReportFormat::from_path("out.md"), ReportFormat::Markdown);
+        assert_eq!(ReportFormat::from_path("out.csv"), ReportFormat::Csv);
+        assert_eq!(ReportFormat::from_path("out"), ReportFormat::Json);
+    }
+}
@@ -0,0 +1,175 @@
+//! Interactive review of packages before starring.
+//!
+//! Packages deselected in a review are ignored permanently: later runs skip them, and later
+//! reviews list them unchecked so that they can be picked again. Reviews in dry runs are not
+//! remembered.
+
+use std::collections::BTreeSet;
+use std::io;
+
+use console::style;
+use dialoguer::{Input, MultiSelect};
+use itertools::Itertools;
+
+use crate::common::Package;
+use crate::{Logger, Persist};
+
+/// Key of the ignore list in the persist store.
+const IGNORED_KEY: &str = "ignored";
+
+/// Packages shown at once in the selection list.
+const PAGE_SIZE: usize = 20;
+
+/// Packages ignored in previous reviews, as `target:identifier`.
+pub struct Ignored(BTreeSet<String>);
+
+impl Ignored {
+    /// Load the ignore list.
+    pub fn load(persist: &Persist) -> Self {
+        Self(persist.get_state(|state| {
+            state
+                .get(IGNORED_KEY)
+                .and_then(|ignored| serde_json::from_value(ignored.clone()).ok())
+                .unwrap_or_default()
+        }))
+    }
+    /// Whether the package is ignored.
+    pub fn contains(&self, package: &Package) -> bool {
+        self.0.contains(&key(package))
+    }
+    /// Ignore deselected packages, and forget selected ones, then save the list.
+    pub fn update(&mut self, persist: &mut Persist, selected: &[Package], deselected: &[Package]) {
+        for package in selected {
+            self.0.remove(&key(package));
+        }
+        self.0.extend(deselected.iter().map(key));
+        let ignored: Vec<_> = self.0.iter().map(String::as_str).collect();
+        persist.with_state(|state| {
+            state.insert(String::from(IGNORED_KEY), ignored.into());
+        });
+    }
+}
+
+fn key(package: &Package) -> String {
+    format!("{}:{}", package.target, package.identifier)
+}
+
+/// Line of the package in the selection list.
+fn label(package: &Package) -> String {
+    format!(
+        "[{}] {}  {} on {}",
+        package.source, package.name, package.identifier, package.target
+    )
+}
+
+/// Case-insensitive substring search.
+fn search(labels: &[String], order: &[usize], query: &str) -> Vec<usize> {
+    let query = query.to_lowercase();
+    order
+        .iter()
+        .copied()
+        .filter(|&idx| labels[idx].to_lowercase().contains(&query))
+        .collect()
+}
+
+/// Let the user pick packages to star.
+///
+/// The list is grouped by source, with ignored packages unchecked. Searches narrow the list down,
+/// and an empty search reviews the whole list and finishes.
+/// Return whether each package is selected, or `None` if the review is cancelled.
+pub fn review(
+    logger: &Logger,
+    packages: &[Package],
+    ignored: &Ignored,
+) -> io::Result<Option<Vec<bool>>> {
+    let labels: Vec<_> = packages.iter().map(label).collect();
+    let order: Vec<_> = (0..packages.len())
+        .sorted_by_key(|&idx| (packages[idx].source, &packages[idx].name))
+        .collect();
+    let mut selected: Vec<_> = packages.iter().map(|p| !ignored.contains(p)).collect();
+
+    logger.pause_progress_bar();
+    let result = loop {
+        let query: String = Input::new()
+            .with_prompt(format!(
+                "{} Search packages (leave empty to review all)",
+                style("?").cyan()
+            ))
+            .allow_empty(true)
+            .interact_text()?;
+        let shown = search(&labels, &order, query.trim());
+        if shown.is_empty() {
+            logger.warn(format!("No package matches {}.", query.trim()));
+            continue;
+        }
+
+        let items: Vec<_> = shown
+            .iter()
+            .map(|&idx| (labels[idx].as_str(), selected[idx]))
+            .collect();
+        let Some(picked) = MultiSelect::new()
+            .with_prompt(format!(
+                "{} Select packages to star (space to toggle, enter to confirm)",
+                style("?").cyan()
+            ))
+            .items_checked(&items)
+            .max_length(PAGE_SIZE)
+            .interact_opt()?
+        else {
+            break None;
+        };
+        for (pos, &idx) in shown.iter().enumerate() {
+            selected[idx] = picked.contains(&pos);
+        }
+
+        if query.trim().is_empty() {
+            break Some(selected);
+        }
+    };
+    logger.resume_progress_bar();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{package, Fixture};
+
+    use super::{label, search, Ignored};
+
+    #[test]
+    fn test_search() {
+        let packages = [
+            package("serde", "serde-rs/serde", "cargo"),
+            package("bash", "gnu/bash", "pacman"),
+            package("serde_json", "serde-rs/json", "cargo"),
+        ];
+        let labels: Vec<_> = packages.iter().map(label).collect();
+        assert_eq!(labels[1], "[pacman] bash  gnu/bash on github");
+
+        let order = [0, 2, 1];
+        assert_eq!(search(&labels, &order, ""), [0, 2, 1]);
+        assert_eq!(search(&labels, &order, "SERDE-RS"), [0, 2]);
+        assert_eq!(search(&labels, &order, "pacman"), [1]);
+        assert!(search(&labels, &order, "zsh").is_empty());
+    }
+
+    #[test]
+    fn test_ignored() {
+        let fixture = Fixture::default();
+        let serde = [package("serde", "serde-rs/serde", "cargo")];
+        let bash = [package("bash", "gnu/bash", "pacman")];
+
+        let mut persist = fixture.persist();
+        let mut ignored = Ignored::load(&persist);
+        ignored.update(&mut persist, &serde, &bash);
+
+        let mut persist = fixture.persist();
+        let mut ignored = Ignored::load(&persist);
+        assert!(ignored.contains(&bash[0]));
+        assert!(!ignored.contains(&serde[0]));
+
+        // Picking an ignored package again forgets it.
+        ignored.update(&mut persist, &bash, &[]);
+        assert!(!Ignored::load(&fixture.persist()).contains(&bash[0]));
+    }
+}
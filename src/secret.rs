@@ -0,0 +1,338 @@
+//! Secret storage.
+//!
+//! Credentials are kept apart from other persisted states in `secrets.json`, which is only
+//! readable by its owner. If `STARS_PASSPHRASE` is set, the file is encrypted with a key derived
+//! from the passphrase, and the passphrase is asked for if an encrypted file is found without it.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{project_dirs, BoxedError};
+use crate::{Logger, Persist};
+
+/// Environment variable holding the passphrase of the secret store.
+pub const PASSPHRASE_ENV: &str = "STARS_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+enum SecretFile {
+    Plain {
+        secrets: BTreeMap<String, String>,
+    },
+    Encrypted {
+        salt: String,
+        nonce: String,
+        ciphertext: String,
+    },
+}
+
+/// Store for credentials.
+#[derive(Default)]
+pub struct SecretStore {
+    secrets: BTreeMap<String, String>,
+    /// `None` for stores living in memory only.
+    path: Option<PathBuf>,
+    passphrase: Option<String>,
+}
+
+impl SecretStore {
+    /// Loads the secrets from default path.
+    pub fn new(logger: &Logger, ignore_exist: bool) -> Result<Self, BoxedError> {
+        let path = project_dirs().config_dir().join("secrets.json");
+        let passphrase = std::env::var(PASSPHRASE_ENV).ok().filter(|s| !s.is_empty());
+        if ignore_exist {
+            return Ok(Self {
+                secrets: BTreeMap::new(),
+                path: Some(path),
+                passphrase,
+            });
+        }
+        Self::from_path(path, passphrase, || {
+            if !io::stdin().is_terminal() {
+                return None;
+            }
+            logger.pause_progress_bar();
+            let passphrase = dialoguer::Password::new()
+                .with_prompt(format!(
+                    "{} Please input the passphrase of your secrets",
+                    style("?").cyan()
+                ))
+                .interact()
+                .ok();
+            logger.resume_progress_bar();
+            passphrase
+        })
+    }
+    /// Loads the secrets from the given path.
+    ///
+    /// `ask_passphrase` is called if the file is encrypted but no passphrase is given.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        mut passphrase: Option<String>,
+        ask_passphrase: impl FnOnce() -> Option<String>,
+    ) -> Result<Self, BoxedError> {
+        let path = path.as_ref();
+        let secrets = match fs::read(path) {
+            Ok(content) => {
+                restrict_permissions(path)?;
+                match serde_json::from_slice(&content)? {
+                    SecretFile::Plain { secrets } => secrets,
+                    SecretFile::Encrypted {
+                        salt,
+                        nonce,
+                        ciphertext,
+                    } => {
+                        if passphrase.is_none() {
+                            passphrase = ask_passphrase();
+                        }
+                        let passphrase = passphrase.as_deref().ok_or_else(|| {
+                            format!("secrets are encrypted, but {} is not set", PASSPHRASE_ENV)
+                        })?;
+                        let cipher = cipher(passphrase, &base64::decode(salt)?)?;
+                        let plaintext = cipher
+                            .decrypt(
+                                Nonce::from_slice(&base64::decode(nonce)?),
+                                base64::decode(ciphertext)?.as_slice(),
+                            )
+                            .map_err(|_| "wrong passphrase, or the secrets are corrupted")?;
+                        serde_json::from_slice(&plaintext)?
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            secrets,
+            path: Some(path.to_path_buf()),
+            passphrase,
+        })
+    }
+    /// Get a secret.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.secrets.get(key).map(String::as_str)
+    }
+    /// Set a secret and save the store to disk.
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), BoxedError> {
+        self.secrets.insert(key.into(), value.into());
+        self.save()
+    }
+    /// Remove a secret and save the store to disk.
+    pub fn remove(&mut self, key: &str) -> Result<(), BoxedError> {
+        if self.secrets.remove(key).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+    /// Move credentials saved by previous versions out of the plaintext persist store.
+    ///
+    /// Return the number of migrated credentials.
+    pub fn migrate(&mut self, persist: &mut Persist) -> Result<usize, BoxedError> {
+        let legacy: Vec<(String, String)> = persist.get_state(|state| {
+            state
+                .iter()
+                .filter(|(key, _)| is_credential_key(key))
+                .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                .collect()
+        });
+        if legacy.is_empty() {
+            return Ok(0);
+        }
+
+        for (key, value) in &legacy {
+            // Secrets saved by this version take precedence.
+            self.secrets
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+        // Only forget plaintext credentials after they are safely stored.
+        self.save()?;
+        persist.with_state(|state| {
+            for (key, _) in &legacy {
+                state.remove(key);
+            }
+        });
+        Ok(legacy.len())
+    }
+
+    fn save(&self) -> Result<(), BoxedError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = if let Some(passphrase) = &self.passphrase {
+            let mut salt = [0; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let ciphertext = cipher(passphrase, &salt)?
+                .encrypt(&nonce, serde_json::to_vec(&self.secrets)?.as_slice())
+                .map_err(|_| "failed to encrypt secrets")?;
+            SecretFile::Encrypted {
+                salt: base64::encode(salt),
+                nonce: base64::encode(nonce),
+                ciphertext: base64::encode(ciphertext),
+            }
+        } else {
+            SecretFile::Plain {
+                secrets: self.secrets.clone(),
+            }
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file next to the store and move it over, so that an interrupted
+        // write never leaves a truncated store behind.
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut f = options.open(&temp)?;
+        // `mode` only applies to new files, and a stale temporary file may be left behind.
+        restrict_permissions(&temp)?;
+        f.write_all(&serde_json::to_vec(&file)?)?;
+        f.sync_all()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
+
+/// Keys of credentials saved in the persist store by previous versions.
+fn is_credential_key(key: &str) -> bool {
+    key.ends_with("_token") || key.ends_with("_credential")
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305, BoxedError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("failed to derive key: {}", e))?;
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+/// Make sure the file is only accessible by its owner.
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    if permissions.mode() & 0o077 != 0 {
+        permissions.set_mode(0o600);
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::{Logger, Persist};
+
+    use super::SecretStore;
+
+    #[test]
+    fn test_plain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+
+        let mut store = SecretStore::from_path(&path, None, || None).unwrap();
+        store.insert("github_credential", "user:token").unwrap();
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        // Loose permissions are fixed on load.
+        #[cfg(unix)]
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let mut store = SecretStore::from_path(&path, None, || None).unwrap();
+        assert_eq!(store.get("github_credential"), Some("user:token"));
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        // Secrets are written to a temporary file first, even if a stale one is left behind.
+        let temp = dir.path().join("secrets.json.tmp");
+        fs::write(&temp, "stale").unwrap();
+        #[cfg(unix)]
+        fs::set_permissions(&temp, fs::Permissions::from_mode(0o644)).unwrap();
+        store.insert("gitlab_token", "glpat-secret").unwrap();
+        assert!(!temp.exists());
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        let store = SecretStore::from_path(&path, None, || None).unwrap();
+        assert_eq!(store.get("gitlab_token"), Some("glpat-secret"));
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+
+        let mut store =
+            SecretStore::from_path(&path, Some(String::from("hunter2")), || None).unwrap();
+        store.insert("gitlab_token", "glpat-secret").unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("glpat-secret"));
+
+        let store = SecretStore::from_path(&path, None, || Some(String::from("hunter2"))).unwrap();
+        assert_eq!(store.get("gitlab_token"), Some("glpat-secret"));
+
+        assert!(SecretStore::from_path(&path, Some(String::from("wrong")), || None).is_err());
+        assert!(SecretStore::from_path(&path, None, || None).is_err());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let logger = Logger::new(true);
+        let dir = tempfile::tempdir().unwrap();
+        let mut persist = Persist::empty(dir.path().join("persist.json"), &logger);
+        persist.with_state(|state| {
+            state.insert(String::from("github_credential"), "user:token".into());
+            state.insert(String::from("codeberg_token"), "secret".into());
+            state.insert(String::from("incremental"), serde_json::json!({}));
+        });
+
+        let path = dir.path().join("secrets.json");
+        let mut store = SecretStore::from_path(&path, None, || None).unwrap();
+        assert_eq!(store.migrate(&mut persist).unwrap(), 2);
+        assert_eq!(store.migrate(&mut persist).unwrap(), 0);
+
+        let store = SecretStore::from_path(&path, None, || None).unwrap();
+        assert_eq!(store.get("github_credential"), Some("user:token"));
+        assert_eq!(store.get("codeberg_token"), Some("secret"));
+        let persisted = fs::read_to_string(dir.path().join("persist.json")).unwrap();
+        assert!(!persisted.contains("token"));
+        assert!(persisted.contains("incremental"));
+    }
+}
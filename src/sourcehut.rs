@@ -0,0 +1,79 @@
+//! SourceHut integration.
+//!
+//! SourceHut has no stars, and its GraphQL APIs don't expose following repositories either.
+//! Packages are still claimed so that they show up as recognized instead of unresolved.
+
+use itertools::Itertools;
+use url::Url;
+
+use crate::common::{Error, Package, StarStatus, Target};
+use crate::credential::Credentials;
+use crate::normalize::{normalize, trim_git_suffix};
+use crate::{Logger, Persist};
+
+/// Hosts of SourceHut services serving repositories.
+const HOSTS: &[&str] = &["git.sr.ht", "hg.sr.ht", "sr.ht"];
+
+pub struct SourceHut;
+
+impl Target for SourceHut {
+    fn name(&self) -> &'static str {
+        "sourcehut"
+    }
+
+    fn init(
+        &mut self,
+        _logger: &Logger,
+        _persist: &mut Persist,
+        _credentials: &Credentials,
+    ) -> bool {
+        true
+    }
+
+    fn try_handle(&self, url: &Url) -> Option<String> {
+        let forge = normalize(url)?;
+        if !HOSTS.contains(&forge.host.as_str()) {
+            return None;
+        }
+
+        // Repositories and projects live under `~user`.
+        let (user, repo) = forge.segments.iter().take(2).collect_tuple()?;
+        if !user.starts_with('~') {
+            return None;
+        }
+        Some(format!("{}/{}", user, trim_git_suffix(repo)))
+    }
+
+    fn star(&self, _logger: &Logger, _package: &Package) -> Result<StarStatus, Error> {
+        Ok(StarStatus::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::Target;
+    use crate::normalize::parse_url;
+
+    use super::SourceHut;
+
+    #[test]
+    fn test_try_handle() {
+        let cases = [
+            ("https://git.sr.ht/~owner/repo", Some("~owner/repo")),
+            (
+                "https://git.sr.ht/~owner/repo/tree/master",
+                Some("~owner/repo"),
+            ),
+            ("git@git.sr.ht:~owner/repo", Some("~owner/repo")),
+            ("https://hg.sr.ht/~owner/repo", Some("~owner/repo")),
+            ("https://sr.ht/~owner/project/", Some("~owner/project")),
+            ("https://git.sr.ht/~owner", None),
+            ("https://man.sr.ht/~owner/wiki", None),
+            ("https://sr.ht/pricing/faq", None),
+        ];
+        for (input, expected) in cases {
+            let url = parse_url(input).unwrap();
+            assert_eq!(SourceHut.try_handle(&url).as_deref(), expected, "{}", input);
+        }
+    }
+}
@@ -1,20 +1,77 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
+use tempfile::TempDir;
 use url::Url;
 
-use crate::common::{BoxedError, Package, Source, Target};
-use crate::{Logger, Persist, TargetRegistry};
+use crate::common::{Candidate, Error, Package, Source, StarStatus, Target};
+use crate::credential::Credentials;
+use crate::registry::TargetRegistry;
+use crate::{Logger, Persist};
+
+/// A package of the GitHub repository `identifier`, found by `source`.
+pub fn package(name: &str, identifier: &str, source: &'static str) -> Package {
+    let mut package = Package::new(
+        String::from(name),
+        Url::parse(&format!("https://github.com/{}", identifier)).unwrap(),
+        String::from(identifier),
+        "github",
+    );
+    package.source = source;
+    package
+}
+
+/// A quiet logger and a temporary directory to keep files of a test in.
+pub struct Fixture {
+    pub dir: TempDir,
+    pub logger: Logger,
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self {
+            dir: tempfile::tempdir().unwrap(),
+            logger: Logger::new(true),
+        }
+    }
+}
+
+impl Fixture {
+    /// Path of a file in the temporary directory.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+    /// Load the persist store, as a new run would.
+    pub fn persist(&self) -> Persist<'_> {
+        Persist::from_path(self.path("persist.json"), &self.logger)
+    }
+    /// Run `f` with an empty target registry on a freshly loaded persist store.
+    pub fn with_registry<R>(&self, f: impl FnOnce(&mut TargetRegistry) -> R) -> R {
+        let mut persist = self.persist();
+        let mut targets = TargetRegistry::new(&self.logger, &mut persist);
+        f(&mut targets)
+    }
+}
 
 #[derive(Default)]
-struct DebugTarget(RefCell<Vec<Package>>);
+pub struct DebugTarget(pub RefCell<Vec<Package>>);
 
 impl Target for DebugTarget {
     fn name(&self) -> &'static str {
         "debug"
     }
 
-    fn init(&mut self, _logger: &Logger, _persist: &mut Persist) -> bool {
+    fn init(
+        &mut self,
+        _logger: &Logger,
+        _persist: &mut Persist,
+        _credentials: &Credentials,
+    ) -> bool {
         true
     }
 
@@ -22,16 +79,16 @@ impl Target for DebugTarget {
         Some(url.to_string())
     }
 
-    fn star(&self, _logger: &Logger, package: &Package) -> Result<(), BoxedError> {
+    fn star(&self, _logger: &Logger, package: &Package) -> Result<StarStatus, Error> {
         self.0.borrow_mut().push(package.clone());
-        Ok(())
+        Ok(StarStatus::Starred)
     }
 }
 
 pub fn test_source(
     source: &impl Source,
     files: HashMap<&str, &[u8]>,
-    assert: impl FnOnce(&[Package]),
+    assert: impl FnOnce(&[Candidate]),
 ) {
     if !source.available() {
         eprintln!("{} not present, skipped", source.name());
@@ -39,12 +96,91 @@ pub fn test_source(
     }
 
     let logger = Logger::new(false);
-    let mut persist = Persist::new(&logger, false);
 
-    let mut targets = TargetRegistry::new(&logger, &mut persist);
-    targets.register(DebugTarget::default());
+    let packages = source.snapshot(&logger, files).unwrap();
 
-    let packages = source.snapshot(&logger, files, &targets).unwrap();
+    assert(&packages);
+}
 
-    assert(&*packages);
+/// A minimal HTTP server on localhost, answering each request with the given handler.
+pub struct MockServer {
+    pub base_url: Url,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+/// A request received by [`MockServer`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub authorization: Option<String>,
+    pub body: String,
+}
+
+impl MockServer {
+    /// Start a server. The handler maps a request to a status code and a response body.
+    pub fn start(handler: impl Fn(&MockRequest) -> (u16, String) + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+
+                let mut authorization = None;
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((key, value)) = header.split_once(':') {
+                        if key.eq_ignore_ascii_case("authorization") {
+                            authorization = Some(value.trim().to_string());
+                        } else if key.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap_or_default();
+                        }
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let request = MockRequest {
+                    method,
+                    path,
+                    authorization,
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                };
+                let (status, body) = handler(&request);
+                received.lock().unwrap().push(request);
+
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        Self { base_url, requests }
+    }
+
+    /// Requests received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
 }
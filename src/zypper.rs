@@ -3,13 +3,13 @@
 use std::collections::HashMap;
 use std::process::Command;
 use std::str;
-use std::str::FromStr;
 
 use regex::Regex;
-use url::Url;
 
-use crate::common::{BoxedError, Package, Source, SourceType};
-use crate::{Logger, TargetRegistry};
+use crate::common::{run_command, Candidate, Error, Source, SourceType};
+use crate::normalize::parse_url;
+use crate::repology::{NameType, RepologyRepo};
+use crate::Logger;
 
 #[derive(Debug)]
 pub struct Zypper;
@@ -31,35 +31,29 @@ impl Source for Zypper {
         &self,
         _logger: &Logger,
         _files: HashMap<&str, &[u8]>,
-        targets: &TargetRegistry,
-    ) -> Result<Vec<Package>, BoxedError> {
+    ) -> Result<Vec<Candidate>, Error> {
         let re_installed = Regex::new(r#"<solvable status="installed" name="([\w-]+)""#).unwrap();
         let re_detail = Regex::new(r#"Name +: (.+)[\s\S]*?URL +: (.+)"#).unwrap();
 
-        let raw_output = Command::new("zypper")
-            .arg("-x")
-            .arg("search")
-            .arg("-i")
-            .output()?
-            .stdout;
-        let output = str::from_utf8(&*raw_output)?;
+        let raw_output = run_command(Command::new("zypper").arg("-x").arg("search").arg("-i"))?;
+        let output = str::from_utf8(&raw_output)?;
         let installed: Vec<_> = re_installed
             .captures_iter(output)
             .map(|cap| cap[1].to_string())
             .collect();
 
-        let raw_output = Command::new("zypper")
-            .arg("info")
-            .args(installed)
-            .output()?
-            .stdout;
-        let output = str::from_utf8(&*raw_output)?;
+        let raw_output = run_command(Command::new("zypper").arg("info").args(installed))?;
+        let output = str::from_utf8(&raw_output)?;
 
         Ok(re_detail
             .captures_iter(output)
-            .filter_map(|cap| targets.try_parse(cap[1].to_string(), &Url::from_str(&cap[2]).ok()?))
+            .map(|cap| Candidate::new(cap[1].to_string(), parse_url(&cap[2]).into_iter().collect()))
             .collect())
     }
+
+    fn repology_repo(&self) -> Option<RepologyRepo> {
+        Some(RepologyRepo::new("opensuse_tumbleweed", NameType::BinName))
+    }
 }
 
 #[cfg(test)]